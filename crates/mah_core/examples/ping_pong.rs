@@ -3,13 +3,13 @@ use std::sync::Arc;
 
 use anyhow::bail;
 use mah_core::adapter::MahSession;
+use mah_core::dispatch::Dispatcher;
 use mah_core::event::MessageOrEvent;
-use mah_core::make_message;
 use mah_core::message::{AnyMessage as _, IncomingMessageNode, Message};
+use mah_core::reply_message;
 use mah_http_adapter::HttpAdapter;
 use mah_webhook_adapter::WebhookAdapterEvents;
 use tokio_util::sync::CancellationToken;
-use tokio_util::task::TaskTracker;
 use trim_in_place::TrimInPlace as _;
 
 #[tokio::main]
@@ -24,22 +24,29 @@ async fn main() -> anyhow::Result<()> {
     let port = args[1].parse()?;
     let endpoint = args[2].parse()?;
     let verify_key = args.get(3);
-    let tracker = TaskTracker::new();
     let token = CancellationToken::new();
     tokio::spawn({
-        let tracker = tracker.clone();
         let token = token.clone();
         async move {
             tokio::signal::ctrl_c().await.unwrap();
-            tracker.close();
             token.cancel();
         }
     });
     let mah = HttpAdapter::new(endpoint, verify_key.cloned());
     let session = Arc::new(mah.verify().await?);
-    let mut events = WebhookAdapterEvents::new().listen((Ipv4Addr::LOCALHOST, port), |err| {
-        eprintln!("{err:?}");
-    })?;
+    let handler = move |event| {
+        let session = session.clone();
+        async move {
+            if let Err(err) = handle_event(session.as_ref(), event).await {
+                eprintln!("{err}");
+            }
+        }
+    };
+    let mut dispatcher = Dispatcher::new(handler.clone());
+    let (_, mut events) =
+        WebhookAdapterEvents::new().listen((Ipv4Addr::LOCALHOST, port), |err| {
+            eprintln!("{err:?}");
+        })?;
     while let Some(event) = loop {
         tokio::select! {
             event = events.recv() => break event,
@@ -49,14 +56,19 @@ async fn main() -> anyhow::Result<()> {
             }
         }
     } {
-        let session = session.clone();
-        tracker.spawn(async move {
-            if let Err(err) = handle_event(session.as_ref(), event).await {
-                eprintln!("{err}");
+        // messages are handled in order within each chat, while events need no ordering
+        let target = match &event {
+            MessageOrEvent::Message(message) => message.reply_target(),
+            MessageOrEvent::Event(_) => None,
+        };
+        match target {
+            Some(target) => dispatcher.dispatch(target, event),
+            None => {
+                tokio::spawn(handler(event));
             }
-        });
+        }
     }
-    tracker.wait().await;
+    dispatcher.join().await;
     Ok(())
 }
 
@@ -71,7 +83,7 @@ async fn handle_event<S: MahSession + ?Sized + 'static>(
             message
                 .sender
                 .handle()
-                .send_message(session, &make_message!["pong"].quote(message.handle()))
+                .send_message(session, &reply_message!(quote = message.handle(); "pong"))
                 .await?;
         }
     }
@@ -3,13 +3,15 @@ use std::num::NonZeroU16;
 
 use async_trait::async_trait;
 pub use bytes::Bytes;
+use futures_util::stream::{BoxStream, TryStreamExt as _};
 use serde::Deserialize;
 use thiserror::Error;
 
 use crate::message::Message;
 use crate::{
     types, AnnouncementDetails, Command, FileDetails, FileUpload, FriendDetails, GroupConfig,
-    GroupDetails, ImageInfo, MemberDetails, MemberInfo, Profile, ShortVideoInfo, VoiceInfo,
+    GroupDetails, ImageInfo, MediaLimits, MemberDetails, MemberInfo, Profile, ProfileUpdate,
+    ShortVideoInfo, UserDetails, VoiceInfo,
 };
 
 #[async_trait]
@@ -22,9 +24,12 @@ pub trait Mah: Sync {
     // endregion
 }
 
+/// Dropping a future returned by any of these methods cancels the underlying request. Read-only
+/// operations are therefore cancel-safe; for mutating operations the request may or may not have
+/// reached the server by the time it is dropped, so the change may or may not have taken effect.
 #[async_trait]
 pub trait MahSession: Sync {
-    type Error: std::error::Error + Send + Sync + 'static;
+    type Error: std::error::Error + From<Unsupported> + Send + Sync + 'static;
 
     // region: message
     async fn get_message_from_id(
@@ -37,10 +42,55 @@ pub trait MahSession: Sync {
         &self,
         args: &types::SendTempMessageArgs,
     ) -> Result<i32, Self::Error>;
+    async fn send_friend_message_detailed(
+        &self,
+        args: &types::SendMessageArgs,
+    ) -> Result<types::SendMessageDetails, Self::Error> {
+        Ok(types::SendMessageDetails {
+            message_id: self.send_friend_message(args).await?,
+            time_secs: None,
+        })
+    }
+    async fn send_group_message_detailed(
+        &self,
+        args: &types::SendMessageArgs,
+    ) -> Result<types::SendMessageDetails, Self::Error> {
+        Ok(types::SendMessageDetails {
+            message_id: self.send_group_message(args).await?,
+            time_secs: None,
+        })
+    }
+    async fn send_temp_message_detailed(
+        &self,
+        args: &types::SendTempMessageArgs,
+    ) -> Result<types::SendMessageDetails, Self::Error> {
+        Ok(types::SendMessageDetails {
+            message_id: self.send_temp_message(args).await?,
+            time_secs: None,
+        })
+    }
     async fn send_other_client_message(
         &self,
         args: &types::SendMessageArgs,
     ) -> Result<i32, Self::Error>;
+    async fn send_raw_friend_message(
+        &self,
+        _args: &types::SendRawMessageArgs,
+    ) -> Result<i32, Self::Error> {
+        Err(Unsupported("raw message chains").into())
+    }
+    async fn send_raw_group_message(
+        &self,
+        _args: &types::SendRawMessageArgs,
+    ) -> Result<i32, Self::Error> {
+        Err(Unsupported("raw message chains").into())
+    }
+    async fn send_raw_temp_message(
+        &self,
+        _args: &types::SendRawTempMessageArgs,
+    ) -> Result<i32, Self::Error> {
+        Err(Unsupported("raw message chains").into())
+    }
     async fn upload_image(
         &self,
         media_type: types::MediaType,
@@ -57,7 +107,17 @@ pub trait MahSession: Sync {
         video: Bytes,
         thumbnail: Bytes,
     ) -> Result<ShortVideoInfo, Self::Error>;
+    /// Adapters check uploads against these limits before sending them, where the size is known.
+    fn media_limits(&self) -> MediaLimits {
+        MediaLimits::DEFAULT
+    }
     async fn recall(&self, args: &types::MessageIdArgs) -> Result<(), Self::Error>;
+    async fn react(&self, _args: &types::ReactionArgs) -> Result<(), Self::Error> {
+        Err(Unsupported("message reactions").into())
+    }
+    async fn unreact(&self, _args: &types::ReactionArgs) -> Result<(), Self::Error> {
+        Err(Unsupported("message reactions").into())
+    }
     async fn nudge(&self, args: &types::NudgeArgs) -> Result<(), Self::Error>;
     async fn roaming_messages(
         &self,
@@ -92,6 +152,9 @@ pub trait MahSession: Sync {
         args: &types::MultiMemberArgs,
     ) -> Result<Vec<MemberDetails>, Self::Error>;
     async fn get_bot_profile(&self) -> Result<Profile, Self::Error>;
+    async fn update_bot_profile(&self, _args: &ProfileUpdate) -> Result<(), Self::Error> {
+        Err(Unsupported("updating the bot profile").into())
+    }
     async fn get_friend_profile(&self, args: &types::TargetArgs) -> Result<Profile, Self::Error>;
     async fn get_member_profile(&self, args: &types::MemberArgs) -> Result<Profile, Self::Error>;
     async fn get_user_profile(&self, args: &types::TargetArgs) -> Result<Profile, Self::Error>;
@@ -127,6 +190,10 @@ pub trait MahSession: Sync {
 
     // region: about
     async fn get_session_info(&self) -> Result<types::GetSessionInfoResult, Self::Error>;
+
+    async fn cached_bot_user(&self) -> Result<UserDetails, Self::Error> {
+        Ok(self.get_session_info().await?.qq)
+    }
     // endregion
 
     // region: file
@@ -136,6 +203,8 @@ pub trait MahSession: Sync {
         args: &types::GetFileInfoArgs,
     ) -> Result<FileDetails, Self::Error>;
     async fn mk_dir(&self, args: &types::MkDirArgs) -> Result<FileDetails, Self::Error>;
+    /// Dropping the future while the file is still being sent aborts the upload; the server
+    /// discards the incomplete request and no file is created.
     async fn upload_file(
         &self,
         group: i64,
@@ -143,6 +212,23 @@ pub trait MahSession: Sync {
         name: Cow<'static, str>,
         file: Bytes,
     ) -> Result<FileDetails, Self::Error>;
+    /// Like [`Self::upload_file`], but reads the `size` bytes of the file from a stream as they are
+    /// sent. The default collects the stream into memory first.
+    async fn upload_file_stream(
+        &self,
+        group: i64,
+        path: Cow<'static, str>,
+        name: Cow<'static, str>,
+        size: u64,
+        file: BoxStream<'static, Result<Bytes, Self::Error>>,
+    ) -> Result<FileDetails, Self::Error> {
+        let mut buf = Vec::with_capacity(size as usize);
+        let mut file = file;
+        while let Some(chunk) = file.try_next().await? {
+            buf.extend_from_slice(&chunk);
+        }
+        self.upload_file(group, path, name, buf.into()).await
+    }
     async fn delete_file(&self, args: &types::FileArgs) -> Result<(), Self::Error>;
     async fn move_file(&self, args: &types::MoveFileArgs) -> Result<(), Self::Error>;
     async fn rename_file(&self, args: &types::RenameFileArgs) -> Result<(), Self::Error>;
@@ -166,6 +252,25 @@ pub trait MahSession: Sync {
     // endregion
 }
 
+/// Status codes documented by mirai-api-http, for comparing against [`Error::code`].
+pub mod error_codes {
+    pub const WRONG_VERIFY_KEY: u16 = 1;
+    pub const BOT_NOT_FOUND: u16 = 2;
+    pub const INVALID_SESSION: u16 = 3;
+    pub const UNVERIFIED_SESSION: u16 = 4;
+    pub const TARGET_NOT_FOUND: u16 = 5;
+    pub const FILE_NOT_FOUND: u16 = 6;
+    pub const PERMISSION_DENIED: u16 = 10;
+    pub const BOT_MUTED: u16 = 20;
+    pub const MESSAGE_TOO_LONG: u16 = 30;
+    pub const BAD_REQUEST: u16 = 400;
+}
+
+/// Returned by the default bodies of the [`MahSession`] methods not every server supports.
+#[derive(Clone, Copy, Debug, Error)]
+#[error("{0} not supported")]
+pub struct Unsupported(pub &'static str);
+
 #[derive(Clone, Debug, Deserialize, Error)]
 #[error("{message}")]
 pub struct Error {
@@ -173,3 +278,62 @@ pub struct Error {
     #[serde(default, rename = "msg")]
     pub message: String,
 }
+
+impl Error {
+    /// mirai-api-http reports every failed send with the same code and puts the exception in the
+    /// message, so this looks for the name of the `SendMessageFailedException` reason in it.
+    pub fn send_message_failure(&self) -> Option<SendMessageFailure> {
+        if self.message.contains("AT_ALL_LIMITED") {
+            Some(SendMessageFailure::AtAllLimited)
+        } else if self.message.contains("GROUP_CHAT_LIMITED") {
+            Some(SendMessageFailure::GroupChatLimited)
+        } else {
+            None
+        }
+    }
+
+    /// mirai-api-http does not expose the group file storage quota, so the only way to notice a
+    /// full storage is through the message of the failed upload, which mirai passes through from
+    /// the server.
+    pub fn upload_file_failure(&self) -> UploadFileFailure {
+        if self.message.contains("空间不足") {
+            UploadFileFailure::StorageFull
+        } else {
+            UploadFileFailure::Other
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SendMessageFailure {
+    AtAllLimited,
+    GroupChatLimited,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UploadFileFailure {
+    StorageFull,
+    /// Any message not recognized above.
+    Other,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, UploadFileFailure};
+
+    fn error(body: &str) -> Error {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn upload_to_full_storage() {
+        let error = error(r#"{"code":500,"msg":"上传失败: 群文件空间不足"}"#);
+        assert_eq!(error.upload_file_failure(), UploadFileFailure::StorageFull);
+    }
+
+    #[test]
+    fn upload_with_unknown_message() {
+        let error = error(r#"{"code":500,"msg":"java.io.IOException: Connection reset"}"#);
+        assert_eq!(error.upload_file_failure(), UploadFileFailure::Other);
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::panic::resume_unwind;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::FutureExt as _;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+struct Worker<T> {
+    tx: mpsc::UnboundedSender<T>,
+    task: JoinHandle<()>,
+}
+
+/// Runs `handler` on items in the order they were dispatched under the same key, and
+/// concurrently across keys. A panic in the handler resurfaces in the next call to
+/// [`dispatch`](Self::dispatch) or [`join`](Self::join).
+pub struct Dispatcher<K, T, H> {
+    handler: Arc<H>,
+    idle_timeout: Duration,
+    workers: HashMap<K, Worker<T>>,
+}
+
+impl<K, T, H, Fut> Dispatcher<K, T, H>
+where
+    K: Eq + Hash,
+    T: Send + 'static,
+    H: Fn(T) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            idle_timeout: Duration::from_secs(60),
+            workers: HashMap::new(),
+        }
+    }
+
+    pub fn idle_timeout(self, idle_timeout: Duration) -> Self {
+        Self {
+            idle_timeout,
+            ..self
+        }
+    }
+
+    pub fn dispatch(&mut self, key: K, item: T) {
+        let mut panic = None;
+        self.workers.retain(|_, worker| {
+            if !worker.task.is_finished() {
+                return true;
+            }
+            if let Some(Err(err)) = (&mut worker.task).now_or_never() {
+                if err.is_panic() {
+                    panic.get_or_insert(err.into_panic());
+                }
+            }
+            false
+        });
+        if let Some(panic) = panic {
+            resume_unwind(panic);
+        }
+        let item = match self.workers.get(&key) {
+            Some(worker) => match worker.tx.send(item) {
+                Ok(()) => return,
+                Err(mpsc::error::SendError(item)) => item,
+            },
+            None => item,
+        };
+        let previous = self.workers.remove(&key).map(|worker| worker.task);
+        let (tx, rx) = mpsc::unbounded_channel();
+        let _ = tx.send(item);
+        let task = tokio::spawn(run_worker(
+            previous,
+            rx,
+            self.handler.clone(),
+            self.idle_timeout,
+        ));
+        self.workers.insert(key, Worker { tx, task });
+    }
+
+    pub async fn join(self) {
+        for (_, Worker { tx, task }) in self.workers {
+            drop(tx);
+            if let Err(err) = task.await {
+                if err.is_panic() {
+                    resume_unwind(err.into_panic());
+                }
+            }
+        }
+    }
+}
+
+async fn run_worker<T, H, Fut>(
+    previous: Option<JoinHandle<()>>,
+    mut rx: mpsc::UnboundedReceiver<T>,
+    handler: Arc<H>,
+    idle_timeout: Duration,
+) where
+    H: Fn(T) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    // a panic is passed on so it still surfaces after the panicked worker has been replaced
+    if let Some(previous) = previous {
+        if let Err(err) = previous.await {
+            if err.is_panic() {
+                resume_unwind(err.into_panic());
+            }
+        }
+    }
+    loop {
+        match timeout(idle_timeout, rx.recv()).await {
+            Ok(Some(item)) => handler(item).await,
+            Ok(None) => return,
+            Err(_) => break,
+        }
+    }
+    rx.close();
+    while let Some(item) = rx.recv().await {
+        handler(item).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::Mutex;
+
+    use futures_util::future::BoxFuture;
+    use tokio::sync::Notify;
+    use tokio::time::sleep;
+
+    use super::*;
+
+    type Log = Arc<Mutex<Vec<(u8, u32)>>>;
+
+    fn log_item(log: &Log) -> impl Fn((u8, u32)) -> BoxFuture<'static, ()> + Send + Sync {
+        let log = log.clone();
+        move |(key, item)| {
+            let log = log.clone();
+            async move {
+                // later items finish sooner, so only the worker keeps them in order
+                sleep(Duration::from_millis(u64::from(10 - item % 10))).await;
+                log.lock().unwrap().push((key, item));
+            }
+            .boxed()
+        }
+    }
+
+    fn items(log: &Log, key: u8) -> Vec<u32> {
+        let log = log.lock().unwrap();
+        log.iter()
+            .filter(|&&(k, _)| k == key)
+            .map(|&(_, item)| item)
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_order_per_key() {
+        let log = Log::default();
+        let mut dispatcher = Dispatcher::new(log_item(&log));
+        for item in 0..20 {
+            dispatcher.dispatch(1, (1, item));
+            dispatcher.dispatch(2, (2, item));
+        }
+        dispatcher.join().await;
+        assert_eq!(items(&log, 1), Vec::from_iter(0..20));
+        assert_eq!(items(&log, 2), Vec::from_iter(0..20));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn evicts_idle_workers() {
+        let log = Log::default();
+        let mut dispatcher = Dispatcher::new(log_item(&log)).idle_timeout(Duration::from_secs(1));
+        dispatcher.dispatch(1, (1, 0));
+        sleep(Duration::from_secs(2)).await;
+        assert!(dispatcher.workers[&1].task.is_finished());
+        dispatcher.dispatch(2, (2, 0));
+        assert_eq!(dispatcher.workers.len(), 1);
+        dispatcher.dispatch(1, (1, 1));
+        dispatcher.join().await;
+        assert_eq!(items(&log, 1), [0, 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replacement_worker_waits_for_previous() {
+        let log = Log::default();
+        let release = Arc::new(Notify::new());
+        let mut dispatcher = Dispatcher::new({
+            let log = log.clone();
+            let release = release.clone();
+            move |item: u32| {
+                let log = log.clone();
+                let release = release.clone();
+                async move {
+                    if item == 0 {
+                        release.notified().await;
+                    }
+                    log.lock().unwrap().push((1, item));
+                }
+            }
+        });
+        dispatcher.dispatch(1, 0);
+        // make the busy worker look like it is shutting down after an idle timeout
+        let (closed, _) = mpsc::unbounded_channel();
+        dispatcher.workers.get_mut(&1).unwrap().tx = closed;
+        dispatcher.dispatch(1, 1);
+        sleep(Duration::from_millis(10)).await;
+        assert!(items(&log, 1).is_empty());
+        release.notify_one();
+        dispatcher.join().await;
+        assert_eq!(items(&log, 1), [0, 1]);
+    }
+
+    async fn panic_on_request(item: &'static str) {
+        if item == "panic" {
+            panic!("handler panicked");
+        }
+    }
+
+    #[tokio::test]
+    async fn join_propagates_panics() {
+        let mut dispatcher = Dispatcher::new(panic_on_request);
+        dispatcher.dispatch(1, "ok");
+        dispatcher.dispatch(2, "panic");
+        let err = tokio::spawn(dispatcher.join()).await.unwrap_err();
+        assert_eq!(
+            *err.into_panic().downcast::<&str>().unwrap(),
+            "handler panicked",
+        );
+    }
+
+    #[tokio::test]
+    async fn dispatch_propagates_panics() {
+        let mut dispatcher = Dispatcher::new(panic_on_request);
+        dispatcher.dispatch(1, "panic");
+        while !dispatcher.workers[&1].task.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        let result = catch_unwind(AssertUnwindSafe(|| dispatcher.dispatch(2, "ok")));
+        assert!(result.is_err());
+        dispatcher.dispatch(1, "ok");
+        dispatcher.join().await;
+    }
+}
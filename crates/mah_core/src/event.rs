@@ -1,7 +1,13 @@
+use std::collections::HashMap;
 use std::time::{Duration, SystemTime};
 
+use async_trait::async_trait;
 use enum_dispatch::enum_dispatch;
+use futures_util::stream::{self, BoxStream, Stream, StreamExt as _};
 use serde::{Deserialize, Deserializer};
+use strum_macros::IntoStaticStr;
+use tokio::sync::mpsc;
+use tokio::time::{timeout_at, Instant};
 
 use crate::adapter::MahSession;
 use crate::message::{
@@ -10,9 +16,9 @@ use crate::message::{
     TempMessage, TempSyncMessage,
 };
 use crate::{
-    types, Bot, FriendDetails, FriendHandle, GroupDetails, GroupHandle, GroupHonor, MemberDetails,
-    MemberHandle, MemberPermission, MessageHandle, OtherClientDetails, StrangerDetails,
-    StrangerHandle, UserHandle,
+    types, util, Bot, FriendDetails, FriendHandle, GroupDetails, GroupHandle, GroupHonor,
+    MemberDetails, MemberHandle, MemberInfo, MemberPermission, MessageHandle, OtherClientDetails,
+    StrangerDetails, StrangerHandle, UserHandle,
 };
 
 #[enum_dispatch]
@@ -101,6 +107,22 @@ pub struct BotPermissionChangeEvent {
     pub current: MemberPermission,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NudgeAction<'a> {
+    pub verb: &'a str,
+    pub suffix: &'a str,
+}
+
+impl NudgeAction<'_> {
+    pub fn render(&self, from: &str, to: &str) -> String {
+        if self.suffix.is_empty() {
+            format!("{from} {} {to}", self.verb)
+        } else {
+            format!("{from} {} {to} {}", self.verb, self.suffix)
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct StrangerNudgeEvent {
     pub context: StrangerDetails,
@@ -111,6 +133,13 @@ pub struct StrangerNudgeEvent {
 }
 
 impl StrangerNudgeEvent {
+    pub fn nudge_action(&self) -> NudgeAction<'_> {
+        NudgeAction {
+            verb: &self.action,
+            suffix: &self.suffix,
+        }
+    }
+
     pub fn from(&self) -> StrangerHandle {
         Bot.get_stranger(self.from_id)
     }
@@ -132,7 +161,8 @@ pub struct FriendMessageRecallEvent {
 
 impl FriendMessageRecallEvent {
     pub fn message(&self) -> Option<MessageHandle> {
-        (self.message_id != 0).then_some(Bot.get_message(self.message_id, self.sender_id))
+        (self.message_id != 0)
+            .then(|| Bot.get_message_in(self.message_id, Bot.get_friend(self.sender_id)))
     }
 
     pub fn sender(&self) -> FriendHandle {
@@ -154,6 +184,13 @@ pub struct FriendNudgeEvent {
 }
 
 impl FriendNudgeEvent {
+    pub fn nudge_action(&self) -> NudgeAction<'_> {
+        NudgeAction {
+            verb: &self.action,
+            suffix: &self.suffix,
+        }
+    }
+
     pub fn from(&self) -> FriendHandle {
         Bot.get_friend(self.from_id)
     }
@@ -206,7 +243,7 @@ pub struct GroupMessageRecallEvent {
 
 impl GroupMessageRecallEvent {
     pub fn message(&self) -> Option<MessageHandle> {
-        (self.message_id != 0).then_some(Bot.get_message(self.message_id, self.context.id))
+        (self.message_id != 0).then(|| Bot.get_message_in(self.message_id, self.context.handle()))
     }
 
     pub fn sender(&self) -> MemberHandle {
@@ -232,6 +269,13 @@ pub struct GroupNudgeEvent {
 }
 
 impl GroupNudgeEvent {
+    pub fn nudge_action(&self) -> NudgeAction<'_> {
+        NudgeAction {
+            verb: &self.action,
+            suffix: &self.suffix,
+        }
+    }
+
     pub fn from(&self) -> MemberHandle {
         self.context.handle().get_member(self.from_id)
     }
@@ -586,6 +630,148 @@ impl BotInvitedJoinGroupRequestEvent {
     }
 }
 
+#[async_trait]
+pub trait PendingRequest {
+    fn requester(&self) -> UserHandle;
+    async fn accept<S: MahSession + ?Sized>(&self, session: &S) -> Result<(), S::Error>;
+    /// Rejects the request. Only member join requests carry `reason` to the requester; friend
+    /// requests and group invitations have no way to send one, so it is dropped.
+    async fn reject<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        reason: Option<&str>,
+    ) -> Result<(), S::Error>;
+}
+
+#[async_trait]
+impl PendingRequest for NewFriendRequestEvent {
+    fn requester(&self) -> UserHandle {
+        self.from()
+    }
+
+    async fn accept<S: MahSession + ?Sized>(&self, session: &S) -> Result<(), S::Error> {
+        self.accept(session).await
+    }
+
+    async fn reject<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        _reason: Option<&str>,
+    ) -> Result<(), S::Error> {
+        self.reject(session, false).await
+    }
+}
+
+#[async_trait]
+impl PendingRequest for MemberJoinRequestEvent {
+    fn requester(&self) -> UserHandle {
+        self.from()
+    }
+
+    async fn accept<S: MahSession + ?Sized>(&self, session: &S) -> Result<(), S::Error> {
+        self.accept(session).await
+    }
+
+    async fn reject<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        reason: Option<&str>,
+    ) -> Result<(), S::Error> {
+        self.reject(session, reason, false).await
+    }
+}
+
+#[async_trait]
+impl PendingRequest for BotInvitedJoinGroupRequestEvent {
+    fn requester(&self) -> UserHandle {
+        self.from()
+    }
+
+    async fn accept<S: MahSession + ?Sized>(&self, session: &S) -> Result<(), S::Error> {
+        self.accept(session).await
+    }
+
+    async fn reject<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        _reason: Option<&str>,
+    ) -> Result<(), S::Error> {
+        self.ignore(session).await
+    }
+}
+
+/// Any of the request events, for handling them without knowing which kind they are.
+#[derive(Clone, Debug)]
+pub enum AnyPendingRequest {
+    NewFriend(NewFriendRequestEvent),
+    MemberJoin(MemberJoinRequestEvent),
+    BotInvitedJoinGroup(BotInvitedJoinGroupRequestEvent),
+}
+
+impl From<NewFriendRequestEvent> for AnyPendingRequest {
+    fn from(value: NewFriendRequestEvent) -> Self {
+        Self::NewFriend(value)
+    }
+}
+
+impl From<MemberJoinRequestEvent> for AnyPendingRequest {
+    fn from(value: MemberJoinRequestEvent) -> Self {
+        Self::MemberJoin(value)
+    }
+}
+
+impl From<BotInvitedJoinGroupRequestEvent> for AnyPendingRequest {
+    fn from(value: BotInvitedJoinGroupRequestEvent) -> Self {
+        Self::BotInvitedJoinGroup(value)
+    }
+}
+
+impl TryFrom<Event> for AnyPendingRequest {
+    type Error = Event;
+
+    fn try_from(value: Event) -> Result<Self, Self::Error> {
+        match value {
+            Event::NewFriendRequest(event) => Ok(Self::NewFriend(event)),
+            Event::MemberJoinRequest(event) => Ok(Self::MemberJoin(event)),
+            Event::BotInvitedJoinGroupRequest(event) => Ok(Self::BotInvitedJoinGroup(event)),
+            event => Err(event),
+        }
+    }
+}
+
+#[async_trait]
+impl PendingRequest for AnyPendingRequest {
+    fn requester(&self) -> UserHandle {
+        match self {
+            Self::NewFriend(event) => PendingRequest::requester(event),
+            Self::MemberJoin(event) => PendingRequest::requester(event),
+            Self::BotInvitedJoinGroup(event) => PendingRequest::requester(event),
+        }
+    }
+
+    async fn accept<S: MahSession + ?Sized>(&self, session: &S) -> Result<(), S::Error> {
+        match self {
+            Self::NewFriend(event) => PendingRequest::accept(event, session).await,
+            Self::MemberJoin(event) => PendingRequest::accept(event, session).await,
+            Self::BotInvitedJoinGroup(event) => PendingRequest::accept(event, session).await,
+        }
+    }
+
+    async fn reject<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        reason: Option<&str>,
+    ) -> Result<(), S::Error> {
+        match self {
+            Self::NewFriend(event) => PendingRequest::reject(event, session, reason).await,
+            Self::MemberJoin(event) => PendingRequest::reject(event, session, reason).await,
+            Self::BotInvitedJoinGroup(event) => {
+                PendingRequest::reject(event, session, reason).await
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct CommandExecutedEvent {
     pub name: String,
@@ -698,6 +884,120 @@ pub enum MessageOrEvent {
     Event(Event),
 }
 
+impl MessageOrEvent {
+    pub fn tag(&self) -> EventTag {
+        match self {
+            Self::Message(Message::Friend(_)) => EventTag::FriendMessage,
+            Self::Message(Message::FriendSync(_)) => EventTag::FriendSyncMessage,
+            Self::Message(Message::Group(_)) => EventTag::GroupMessage,
+            Self::Message(Message::GroupSync(_)) => EventTag::GroupSyncMessage,
+            Self::Message(Message::Temp(_)) => EventTag::TempMessage,
+            Self::Message(Message::TempSync(_)) => EventTag::TempSyncMessage,
+            Self::Message(Message::Stranger(_)) => EventTag::StrangerMessage,
+            Self::Message(Message::StrangerSync(_)) => EventTag::StrangerSyncMessage,
+            Self::Message(Message::OtherClient(_)) => EventTag::OtherClientMessage,
+            Self::Event(Event::BotOnline(_)) => EventTag::BotOnline,
+            Self::Event(Event::BotOfflineActive(_)) => EventTag::BotOfflineActive,
+            Self::Event(Event::BotOfflineForced(_)) => EventTag::BotOfflineForced,
+            Self::Event(Event::BotOfflineDropped(_)) => EventTag::BotOfflineDropped,
+            Self::Event(Event::BotRelogin(_)) => EventTag::BotRelogin,
+            Self::Event(Event::BotMute(_)) => EventTag::BotMute,
+            Self::Event(Event::BotUnmute(_)) => EventTag::BotUnmute,
+            Self::Event(Event::BotJoinGroup(_)) => EventTag::BotJoinGroup,
+            Self::Event(Event::BotLeaveGroupActive(_)) => EventTag::BotLeaveGroupActive,
+            Self::Event(Event::BotLeaveGroupKicked(_)) => EventTag::BotLeaveGroupKicked,
+            Self::Event(Event::BotLeaveGroupDisband(_)) => EventTag::BotLeaveGroupDisband,
+            Self::Event(Event::BotPermissionChange(_)) => EventTag::BotPermissionChange,
+            Self::Event(Event::StrangerNudge(_)) => EventTag::StrangerNudge,
+            Self::Event(Event::FriendMessageRecall(_)) => EventTag::FriendMessageRecall,
+            Self::Event(Event::FriendNudge(_)) => EventTag::FriendNudge,
+            Self::Event(Event::FriendAdd(_)) => EventTag::FriendAdd,
+            Self::Event(Event::FriendDelete(_)) => EventTag::FriendDelete,
+            Self::Event(Event::FriendNicknameChange(_)) => EventTag::FriendNicknameChange,
+            Self::Event(Event::FriendTyping(_)) => EventTag::FriendTyping,
+            Self::Event(Event::GroupMessageRecall(_)) => EventTag::GroupMessageRecall,
+            Self::Event(Event::GroupNudge(_)) => EventTag::GroupNudge,
+            Self::Event(Event::GroupNameChange(_)) => EventTag::GroupNameChange,
+            Self::Event(Event::GroupMuteAll(_)) => EventTag::GroupMuteAll,
+            Self::Event(Event::GroupAllowAnonymousChat(_)) => EventTag::GroupAllowAnonymousChat,
+            Self::Event(Event::GroupAllowConfessTalk(_)) => EventTag::GroupAllowConfessTalk,
+            Self::Event(Event::GroupAllowMemberInvite(_)) => EventTag::GroupAllowMemberInvite,
+            Self::Event(Event::MemberMute(_)) => EventTag::MemberMute,
+            Self::Event(Event::MemberUnmute(_)) => EventTag::MemberUnmute,
+            Self::Event(Event::MemberJoin(_)) => EventTag::MemberJoin,
+            Self::Event(Event::MemberLeaveActive(_)) => EventTag::MemberLeaveActive,
+            Self::Event(Event::MemberLeaveKicked(_)) => EventTag::MemberLeaveKicked,
+            Self::Event(Event::MemberNameChange(_)) => EventTag::MemberNameChange,
+            Self::Event(Event::MemberSpecialTitleChange(_)) => EventTag::MemberSpecialTitleChange,
+            Self::Event(Event::MemberPermissionChange(_)) => EventTag::MemberPermissionChange,
+            Self::Event(Event::MemberHonorChange(_)) => EventTag::MemberHonorChange,
+            Self::Event(Event::OtherClientOnline(_)) => EventTag::OtherClientOnline,
+            Self::Event(Event::OtherClientOffline(_)) => EventTag::OtherClientOffline,
+            Self::Event(Event::NewFriendRequest(_)) => EventTag::NewFriendRequest,
+            Self::Event(Event::MemberJoinRequest(_)) => EventTag::MemberJoinRequest,
+            Self::Event(Event::BotInvitedJoinGroupRequest(_)) => {
+                EventTag::BotInvitedJoinGroupRequest
+            }
+            Self::Event(Event::CommandExecuted(_)) => EventTag::CommandExecuted,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, IntoStaticStr)]
+#[non_exhaustive]
+pub enum EventTag {
+    FriendMessage,
+    FriendSyncMessage,
+    GroupMessage,
+    GroupSyncMessage,
+    TempMessage,
+    TempSyncMessage,
+    StrangerMessage,
+    StrangerSyncMessage,
+    OtherClientMessage,
+    BotOnline,
+    BotOfflineActive,
+    BotOfflineForced,
+    BotOfflineDropped,
+    BotRelogin,
+    BotMute,
+    BotUnmute,
+    BotJoinGroup,
+    BotLeaveGroupActive,
+    BotLeaveGroupKicked,
+    BotLeaveGroupDisband,
+    BotPermissionChange,
+    StrangerNudge,
+    FriendMessageRecall,
+    FriendNudge,
+    FriendAdd,
+    FriendDelete,
+    FriendNicknameChange,
+    FriendTyping,
+    GroupMessageRecall,
+    GroupNudge,
+    GroupNameChange,
+    GroupMuteAll,
+    GroupAllowAnonymousChat,
+    GroupAllowConfessTalk,
+    GroupAllowMemberInvite,
+    MemberMute,
+    MemberUnmute,
+    MemberJoin,
+    MemberLeaveActive,
+    MemberLeaveKicked,
+    MemberNameChange,
+    MemberSpecialTitleChange,
+    MemberPermissionChange,
+    MemberHonorChange,
+    OtherClientOnline,
+    OtherClientOffline,
+    NewFriendRequest,
+    MemberJoinRequest,
+    BotInvitedJoinGroupRequest,
+    CommandExecuted,
+}
+
 impl<'de> Deserialize<'de> for MessageOrEvent {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
         #[derive(Debug, Deserialize)]
@@ -848,3 +1148,592 @@ impl<'de> Deserialize<'de> for MessageOrEvent {
         })
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct RawMessageOrEvent {
+    pub event: MessageOrEvent,
+    pub raw: Box<serde_json::value::RawValue>,
+}
+
+impl<'de> Deserialize<'de> for RawMessageOrEvent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let raw = Box::<serde_json::value::RawValue>::deserialize(deserializer)?;
+        let event = serde_json::from_str(raw.get()).map_err(D::Error::custom)?;
+        Ok(Self { event, raw })
+    }
+}
+
+/// A source of incoming messages and events, independent of the transport delivering them. The
+/// receivers returned by the adapters' `listen` methods implement this, so code consuming events
+/// can be written once and used with any adapter.
+pub trait EventSource {
+    fn listen(self) -> BoxStream<'static, MessageOrEvent>;
+}
+
+impl EventSource for mpsc::Receiver<MessageOrEvent> {
+    fn listen(mut self) -> BoxStream<'static, MessageOrEvent> {
+        stream::poll_fn(move |cx| self.poll_recv(cx)).boxed()
+    }
+}
+
+impl EventSource for mpsc::UnboundedReceiver<MessageOrEvent> {
+    fn listen(mut self) -> BoxStream<'static, MessageOrEvent> {
+        stream::poll_fn(move |cx| self.poll_recv(cx)).boxed()
+    }
+}
+
+impl EventSource for BoxStream<'static, MessageOrEvent> {
+    fn listen(self) -> BoxStream<'static, MessageOrEvent> {
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TypingSignal {
+    Started(FriendDetails),
+    Stopped(FriendDetails),
+}
+
+pub fn typing_sessions<St: Stream<Item = MessageOrEvent> + Unpin>(
+    events: St,
+    grace: Duration,
+) -> impl Stream<Item = TypingSignal> {
+    let active = HashMap::<i64, (FriendDetails, Option<Instant>)>::new();
+    stream::unfold(
+        (events.fuse(), active),
+        move |(mut events, mut active)| async move {
+            loop {
+                let deadline = active
+                    .iter()
+                    .filter_map(|(&id, &(_, deadline))| Some((id, deadline?)))
+                    .min_by_key(|&(_, deadline)| deadline);
+                let event = match deadline {
+                    Some((id, deadline)) => match timeout_at(deadline, events.next()).await {
+                        Ok(event) => event,
+                        Err(_) => {
+                            let (friend, _) = active.remove(&id)?;
+                            return Some((TypingSignal::Stopped(friend), (events, active)));
+                        }
+                    },
+                    None => events.next().await,
+                };
+                let Some(event) = event else {
+                    let id = *active.keys().next()?;
+                    let (friend, _) = active.remove(&id)?;
+                    return Some((TypingSignal::Stopped(friend), (events, active)));
+                };
+                let MessageOrEvent::Event(Event::FriendTyping(FriendTypingEvent {
+                    friend,
+                    typing,
+                })) = event
+                else {
+                    continue;
+                };
+                match (active.get_mut(&friend.0.id), typing) {
+                    (Some((_, deadline)), true) => *deadline = None,
+                    (Some((_, deadline)), false) => *deadline = Some(Instant::now() + grace),
+                    (None, true) => {
+                        active.insert(friend.0.id, (friend.clone(), None));
+                        return Some((TypingSignal::Started(friend), (events, active)));
+                    }
+                    (None, false) => {}
+                }
+            }
+        },
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InviteDecision {
+    Accepted,
+    Ignored,
+}
+
+pub fn auto_handle_invites<'a, St, S, P, D>(
+    events: St,
+    session: &'a S,
+    trusted: P,
+    on_decision: D,
+) -> impl Stream<Item = MessageOrEvent> + 'a
+where
+    St: Stream<Item = MessageOrEvent> + 'a,
+    S: MahSession + ?Sized,
+    P: FnMut(&BotInvitedJoinGroupRequestEvent) -> bool + 'a,
+    D: FnMut(&BotInvitedJoinGroupRequestEvent, Result<InviteDecision, S::Error>) + 'a,
+{
+    let state = (Box::pin(events), trusted, on_decision);
+    stream::unfold(
+        state,
+        move |(mut events, mut trusted, mut on_decision)| async move {
+            loop {
+                let request = match events.next().await? {
+                    MessageOrEvent::Event(Event::BotInvitedJoinGroupRequest(request)) => request,
+                    event => return Some((event, (events, trusted, on_decision))),
+                };
+                let result = if trusted(&request) {
+                    request
+                        .accept(session)
+                        .await
+                        .map(|()| InviteDecision::Accepted)
+                } else {
+                    request
+                        .ignore(session)
+                        .await
+                        .map(|()| InviteDecision::Ignored)
+                };
+                on_decision(&request, result);
+            }
+        },
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HonorChange {
+    pub member: MemberHandle,
+    pub honor: GroupHonor,
+    pub action: MemberHonorChangeAction,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HonorTracker {
+    /// When each honor was achieved, if that happened while tracking.
+    honors: HashMap<MemberHandle, HashMap<GroupHonor, Option<SystemTime>>>,
+}
+
+impl HonorTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, info: &MemberInfo) {
+        let member = info.details.handle();
+        let previous = self.honors.remove(&member).unwrap_or_default();
+        let honors = info
+            .activity
+            .honors
+            .iter()
+            .map(|&honor| (honor, previous.get(&honor).copied().flatten()))
+            .collect::<HashMap<_, _>>();
+        if !honors.is_empty() {
+            self.honors.insert(member, honors);
+        }
+    }
+
+    pub fn update(&mut self, event: &MessageOrEvent) -> Option<HonorChange> {
+        let MessageOrEvent::Event(event) = event else {
+            return None;
+        };
+        let MemberHonorChangeEvent {
+            member,
+            action,
+            honor,
+        } = match event {
+            Event::MemberHonorChange(event) => event,
+            Event::MemberLeaveActive(MemberLeaveActiveEvent { member })
+            | Event::MemberLeaveKicked(MemberLeaveKickedEvent { member, .. }) => {
+                self.honors.remove(&member.handle());
+                return None;
+            }
+            Event::BotLeaveGroupActive(BotLeaveGroupActiveEvent { group })
+            | Event::BotLeaveGroupKicked(BotLeaveGroupKickedEvent { group, .. })
+            | Event::BotLeaveGroupDisband(BotLeaveGroupDisbandEvent { group, .. }) => {
+                let group = group.handle();
+                self.honors.retain(|member, _| member.group() != group);
+                return None;
+            }
+            _ => return None,
+        };
+        let member = member.handle();
+        let changed = match action {
+            MemberHonorChangeAction::Achieve => {
+                let honors = self.honors.entry(member).or_default();
+                let new = !honors.contains_key(honor);
+                if new {
+                    honors.insert(*honor, Some(SystemTime::now()));
+                }
+                new
+            }
+            MemberHonorChangeAction::Lose => match self.honors.get_mut(&member) {
+                Some(honors) => {
+                    let removed = honors.remove(honor).is_some();
+                    if honors.is_empty() {
+                        self.honors.remove(&member);
+                    }
+                    removed
+                }
+                None => false,
+            },
+        };
+        changed.then_some(HonorChange {
+            member,
+            honor: *honor,
+            action: *action,
+        })
+    }
+
+    pub fn honors(&self, member: MemberHandle) -> impl Iterator<Item = GroupHonor> + '_ {
+        self.honors
+            .get(&member)
+            .into_iter()
+            .flat_map(|honors| honors.keys().copied())
+    }
+
+    pub fn holders(
+        &self,
+        group: GroupHandle,
+        honor: GroupHonor,
+    ) -> impl Iterator<Item = MemberHandle> + '_ {
+        self.honors
+            .iter()
+            .filter(move |(member, honors)| member.group() == group && honors.contains_key(&honor))
+            .map(|(&member, _)| member)
+    }
+
+    /// When `member` achieved `honor`, if it was seen through [`update`](Self::update) rather
+    /// than only in a snapshot.
+    pub fn held_since(&self, member: MemberHandle, honor: GroupHonor) -> Option<SystemTime> {
+        *self.honors.get(&member)?.get(&honor)?
+    }
+
+    /// How long `member` has held `honor`, formatted like `1d 2h`.
+    pub fn held_for_display(&self, member: MemberHandle, honor: GroupHonor) -> Option<String> {
+        let held_for = self
+            .held_since(member, honor)?
+            .elapsed()
+            .unwrap_or_default();
+        Some(util::format_duration(held_for))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ActivityChange {
+    pub member: MemberHandle,
+    pub points: i32,
+    pub temperature: i32,
+}
+
+/// Tracks changes in members' activity points and temperature across periodic
+/// [`MemberInfo`] fetches, relative to the first snapshot recorded for each member or the last
+/// [`reset`](Self::reset).
+#[derive(Clone, Debug, Default)]
+pub struct ActivityTracker {
+    baseline: HashMap<MemberHandle, (i32, i32)>,
+    latest: HashMap<MemberHandle, (i32, i32)>,
+}
+
+impl ActivityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the change since the previous snapshot of the same member, if there was one.
+    pub fn record(&mut self, info: &MemberInfo) -> Option<ActivityChange> {
+        let member = info.details.handle();
+        let current = (info.activity.points, info.activity.temperature);
+        self.baseline.entry(member).or_insert(current);
+        let previous = self.latest.insert(member, current)?;
+        Some(ActivityChange {
+            member,
+            points: current.0 - previous.0,
+            temperature: current.1 - previous.1,
+        })
+    }
+
+    pub fn update(&mut self, event: &MessageOrEvent) {
+        match event {
+            MessageOrEvent::Event(
+                Event::MemberLeaveActive(MemberLeaveActiveEvent { member })
+                | Event::MemberLeaveKicked(MemberLeaveKickedEvent { member, .. }),
+            ) => {
+                self.baseline.remove(&member.handle());
+                self.latest.remove(&member.handle());
+            }
+            MessageOrEvent::Event(
+                Event::BotLeaveGroupActive(BotLeaveGroupActiveEvent { group })
+                | Event::BotLeaveGroupKicked(BotLeaveGroupKickedEvent { group, .. })
+                | Event::BotLeaveGroupDisband(BotLeaveGroupDisbandEvent { group, .. }),
+            ) => {
+                let group = group.handle();
+                self.baseline.retain(|member, _| member.group() != group);
+                self.latest.retain(|member, _| member.group() != group);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn change(&self, member: MemberHandle) -> Option<ActivityChange> {
+        let baseline = self.baseline.get(&member)?;
+        let latest = self.latest.get(&member)?;
+        Some(ActivityChange {
+            member,
+            points: latest.0 - baseline.0,
+            temperature: latest.1 - baseline.1,
+        })
+    }
+
+    /// The `count` members of `group` whose points grew the most, largest gain first.
+    pub fn top_movers(&self, group: GroupHandle, count: usize) -> Vec<ActivityChange> {
+        let mut changes = self
+            .latest
+            .keys()
+            .filter(|member| member.group() == group)
+            .filter_map(|&member| self.change(member))
+            .collect::<Vec<_>>();
+        changes.sort_by_key(|change| std::cmp::Reverse(change.points));
+        changes.truncate(count);
+        changes
+    }
+
+    pub fn reset(&mut self) {
+        self.baseline.clone_from(&self.latest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::time::Duration;
+
+    use futures_util::StreamExt as _;
+    use tokio::sync::mpsc;
+    use tokio::time::{sleep, Instant};
+
+    use super::{
+        typing_sessions, ActivityChange, ActivityTracker, Event, EventSource as _,
+        FriendTypingEvent, HonorChange, HonorTracker, MemberHonorChangeAction,
+        MemberHonorChangeEvent, MemberLeaveKickedEvent, MessageOrEvent, TypingSignal,
+    };
+    use crate::{
+        FriendDetails, GroupDetails, GroupHonor, MemberActivity, MemberDetails, MemberInfo,
+        MemberPermission, UserDetails,
+    };
+
+    const GROUP: i64 = 987654321;
+
+    fn member(id: i64) -> MemberDetails {
+        MemberDetails {
+            id,
+            member_name: "Member".into(),
+            special_title: String::new(),
+            permission: MemberPermission::Member,
+            join_time_secs: 1600000000,
+            last_speak_time_secs: 1700000000,
+            mute_time_remaining_secs: 0,
+            group: GroupDetails {
+                id: GROUP,
+                name: "Group".into(),
+                permission: MemberPermission::Admin,
+            },
+        }
+    }
+
+    fn member_info(id: i64, points: i32, temperature: i32, honors: &[GroupHonor]) -> MemberInfo {
+        MemberInfo {
+            details: member(id),
+            activity: MemberActivity {
+                rank: 1,
+                points,
+                honors: honors.to_vec(),
+                temperature,
+            },
+        }
+    }
+
+    fn honor_change(id: i64, action: MemberHonorChangeAction, honor: GroupHonor) -> MessageOrEvent {
+        MessageOrEvent::Event(Event::MemberHonorChange(MemberHonorChangeEvent {
+            member: member(id),
+            action,
+            honor,
+        }))
+    }
+
+    fn kicked(id: i64) -> MessageOrEvent {
+        MessageOrEvent::Event(Event::MemberLeaveKicked(MemberLeaveKickedEvent {
+            member: member(id),
+            operator: None,
+        }))
+    }
+
+    #[test]
+    fn honor_tracker_follows_changes() {
+        let group = member(1).group.handle();
+        let talkative = |tracker: &HonorTracker| {
+            let mut holders = tracker
+                .holders(group, GroupHonor::Talkative)
+                .map(|member| member.id)
+                .collect::<Vec<_>>();
+            holders.sort();
+            holders
+        };
+        let mut tracker = HonorTracker::new();
+        tracker.insert(&member_info(1, 0, 0, &[GroupHonor::Talkative]));
+        assert_eq!(talkative(&tracker), [1]);
+
+        let change = tracker.update(&honor_change(
+            2,
+            MemberHonorChangeAction::Achieve,
+            GroupHonor::Talkative,
+        ));
+        assert_eq!(
+            change,
+            Some(HonorChange {
+                member: member(2).handle(),
+                honor: GroupHonor::Talkative,
+                action: MemberHonorChangeAction::Achieve,
+            })
+        );
+        assert_eq!(talkative(&tracker), [1, 2]);
+        // achieving an honor already held changes nothing
+        let again = honor_change(2, MemberHonorChangeAction::Achieve, GroupHonor::Talkative);
+        assert_eq!(tracker.update(&again), None);
+
+        let lose = honor_change(1, MemberHonorChangeAction::Lose, GroupHonor::Talkative);
+        assert!(tracker.update(&lose).is_some());
+        assert_eq!(tracker.update(&lose), None);
+        assert_eq!(talkative(&tracker), [2]);
+
+        assert_eq!(tracker.update(&kicked(2)), None);
+        assert_eq!(talkative(&tracker), [] as [i64; 0]);
+    }
+
+    #[test]
+    fn honor_tracker_times_only_seen_achievements() {
+        let snapshot = member(1).handle();
+        let seen = member(2).handle();
+        let mut tracker = HonorTracker::new();
+        tracker.insert(&member_info(1, 0, 0, &[GroupHonor::Talkative]));
+        tracker.update(&honor_change(
+            2,
+            MemberHonorChangeAction::Achieve,
+            GroupHonor::Talkative,
+        ));
+        assert_eq!(tracker.held_since(snapshot, GroupHonor::Talkative), None);
+        assert_eq!(
+            tracker.held_for_display(snapshot, GroupHonor::Talkative),
+            None
+        );
+        assert!(tracker.held_since(seen, GroupHonor::Talkative).is_some());
+        assert_eq!(
+            tracker
+                .held_for_display(seen, GroupHonor::Talkative)
+                .as_deref(),
+            Some("0s")
+        );
+        // a later snapshot keeps the time the honor was seen achieved
+        tracker.insert(&member_info(2, 0, 0, &[GroupHonor::Talkative]));
+        assert!(tracker.held_since(seen, GroupHonor::Talkative).is_some());
+        assert_eq!(
+            tracker.honors(seen).collect::<Vec<_>>(),
+            [GroupHonor::Talkative]
+        );
+    }
+
+    #[test]
+    fn activity_tracker_folds_snapshots() {
+        let alice = member(1).handle();
+        let bob = member(2).handle();
+        let mut tracker = ActivityTracker::new();
+        assert_eq!(tracker.record(&member_info(1, 100, 50, &[])), None);
+        assert_eq!(tracker.record(&member_info(2, 100, 50, &[])), None);
+        assert_eq!(
+            tracker.record(&member_info(1, 130, 45, &[])),
+            Some(ActivityChange {
+                member: alice,
+                points: 30,
+                temperature: -5,
+            })
+        );
+        assert_eq!(
+            tracker.record(&member_info(1, 140, 60, &[])),
+            Some(ActivityChange {
+                member: alice,
+                points: 10,
+                temperature: 15,
+            })
+        );
+        tracker.record(&member_info(2, 120, 50, &[]));
+        // both snapshots since the first add up
+        assert_eq!(
+            tracker.change(alice),
+            Some(ActivityChange {
+                member: alice,
+                points: 40,
+                temperature: 10,
+            })
+        );
+        let movers = tracker.top_movers(alice.group(), 2);
+        assert_eq!(
+            movers
+                .iter()
+                .map(|change| change.member)
+                .collect::<Vec<_>>(),
+            [alice, bob]
+        );
+        assert_eq!(tracker.top_movers(alice.group(), 1).len(), 1);
+
+        tracker.reset();
+        assert_eq!(tracker.change(alice).map(|change| change.points), Some(0));
+        tracker.record(&member_info(1, 135, 60, &[]));
+        assert_eq!(tracker.change(alice).map(|change| change.points), Some(-5));
+
+        tracker.update(&kicked(1));
+        assert_eq!(tracker.change(alice), None);
+        assert_eq!(tracker.record(&member_info(1, 0, 0, &[])), None);
+    }
+
+    fn typing(id: i64, typing: bool) -> MessageOrEvent {
+        let friend = FriendDetails(UserDetails {
+            id,
+            nickname: "Friend".into(),
+            remark: String::new(),
+        });
+        MessageOrEvent::Event(Event::FriendTyping(FriendTypingEvent { friend, typing }))
+    }
+
+    #[tokio::test]
+    async fn typing_sessions_debounces_toggles() {
+        tokio::time::pause();
+        let start = Instant::now();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut signals = pin!(typing_sessions(rx.listen(), Duration::from_secs(5)));
+        tx.send(typing(1, true)).unwrap();
+        let Some(TypingSignal::Started(friend)) = signals.next().await else {
+            panic!("expected typing to start");
+        };
+        assert_eq!(friend.0.id, 1);
+
+        let toggles = tx.clone();
+        tokio::spawn(async move {
+            // stopping without having started is ignored
+            toggles.send(typing(2, false)).unwrap();
+            for typing_now in [false, true, false, true, false] {
+                sleep(Duration::from_secs(1)).await;
+                toggles.send(typing(1, typing_now)).unwrap();
+            }
+        });
+        // the toggles each came within the grace period of the last stop, at 5s
+        let Some(TypingSignal::Stopped(friend)) = signals.next().await else {
+            panic!("expected typing to stop");
+        };
+        assert_eq!(friend.0.id, 1);
+        // paused timers still round up to the next millisecond
+        let stopped_at = start.elapsed();
+        assert!((Duration::from_secs(10)..Duration::from_millis(10100)).contains(&stopped_at));
+
+        tx.send(typing(1, true)).unwrap();
+        assert!(matches!(
+            signals.next().await,
+            Some(TypingSignal::Started(_))
+        ));
+        // typing friends are reported stopped when the events end
+        drop(tx);
+        assert!(matches!(
+            signals.next().await,
+            Some(TypingSignal::Stopped(_))
+        ));
+        assert!(signals.next().await.is_none());
+        assert_eq!(start.elapsed(), stopped_at);
+    }
+}
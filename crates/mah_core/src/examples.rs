@@ -0,0 +1,214 @@
+//! Canonical JSON for the args in [`types`], as sent to mirai-api-http. Handy
+//! for checking why a payload was rejected or for writing bindings in other languages.
+//!
+//! Args only ever sent as a query string, such as [`MultiMemberArgs`](types::MultiMemberArgs)
+//! and [`CountArgs`](types::CountArgs), are not listed.
+
+use serde::Serialize;
+
+use crate::message::{at, OutgoingMessageContents, OutgoingMessageNode};
+use crate::types::{self, FileLocator};
+use crate::{Announcement, Bot, GroupConfigUpdate, MemberInfoUpdate};
+
+#[derive(Clone, Debug)]
+pub struct Example {
+    pub type_name: &'static str,
+    pub json: String,
+}
+
+fn example<T: Serialize>(value: &T) -> Example {
+    let type_name = std::any::type_name::<T>();
+    Example {
+        type_name: type_name.rsplit("::").next().unwrap_or(type_name),
+        json: serde_json::to_string(value).unwrap(),
+    }
+}
+
+/// One example of each args type, in the order they are declared.
+pub fn args() -> Vec<Example> {
+    let nodes: [OutgoingMessageNode; 2] = [at(123456789).into(), " hello".into()];
+    let contents =
+        OutgoingMessageContents::new(&nodes).quote_handle(Bot.get_message(4321, 987654321));
+    let raw_chain = serde_json::json!([{ "type": "Plain", "text": "hello" }]);
+    let config = GroupConfigUpdate::new().name(Some("group"));
+    let info = MemberInfoUpdate::new().special_title(Some("title"));
+    let announcement = Announcement::new("hello").pinned(true);
+    vec![
+        example(&types::VerifyArgs { verify_key: "key" }),
+        example(&types::BindArgs { qq: 10000 }),
+        example(&types::TargetArgs { target: 123456789 }),
+        example(&types::NudgeArgs {
+            target: 123456789,
+            subject: 987654321,
+            kind: types::SubjectKind::Group,
+        }),
+        example(&types::SendMessageArgs {
+            target: 987654321,
+            contents: &contents,
+        }),
+        example(&types::SendTempMessageArgs {
+            qq: 123456789,
+            group: 987654321,
+            contents: &contents,
+        }),
+        example(&types::SendRawMessageArgs {
+            target: 987654321,
+            message_chain: &raw_chain,
+        }),
+        example(&types::SendRawTempMessageArgs {
+            qq: 123456789,
+            group: 987654321,
+            message_chain: &raw_chain,
+        }),
+        example(&types::MessageIdArgs {
+            target: 987654321,
+            message_id: 4321,
+        }),
+        example(&types::ReactionArgs {
+            target: 987654321,
+            message_id: 4321,
+            face_id: 76,
+        }),
+        example(&types::RoamingMessagesArgs {
+            time_start: 1700000000,
+            time_end: 1700086400,
+            target: types::RoamingMessagesTarget::Friend(123456789),
+        }),
+        example(&types::HandleNewFriendRequestArgs {
+            event_id: 1,
+            from_id: 123456789,
+            operation: types::NewFriendRequestOperation::Accept,
+        }),
+        example(&types::HandleMemberJoinRequestArgs {
+            event_id: 1,
+            from_id: 123456789,
+            group_id: 987654321,
+            operation: types::MemberJoinRequestOperation::Reject,
+            message: "no",
+        }),
+        example(&types::HandleBotInvitedJoinGroupRequestArgs {
+            event_id: 1,
+            from_id: 123456789,
+            group_id: 987654321,
+            operation: types::BotInvitedJoinGroupRequestOperation::Accept,
+        }),
+        example(&types::MuteArgs {
+            target: 987654321,
+            member_id: 123456789,
+            time: 600,
+        }),
+        example(&types::KickArgs {
+            target: 987654321,
+            member_id: 123456789,
+            block: true,
+            msg: "bye",
+        }),
+        example(&types::ModifyMemberAdminArgs {
+            target: 987654321,
+            member_id: 123456789,
+            assign: true,
+        }),
+        example(&types::UpdateGroupConfigArgs {
+            target: 987654321,
+            config: &config,
+        }),
+        example(&types::MemberArgs {
+            target: 987654321,
+            member_id: 123456789,
+        }),
+        example(&types::UpdateMemberInfoArgs {
+            target: 987654321,
+            member_id: 123456789,
+            info: &info,
+        }),
+        example(&types::FileArgs {
+            file: FileLocator::Id("/abc"),
+            target: 987654321,
+        }),
+        example(&types::ListFileArgs {
+            directory: FileLocator::root(),
+            target: 987654321,
+            offset: 0,
+            size: Some(10),
+            with_download_info: true,
+        }),
+        example(&types::GetFileInfoArgs {
+            file: FileLocator::Path("docs/a.txt"),
+            target: 987654321,
+            with_download_info: false,
+        }),
+        example(&types::MkDirArgs {
+            directory: FileLocator::root(),
+            target: 987654321,
+            directory_name: "docs",
+        }),
+        example(&types::RenameFileArgs {
+            file: FileLocator::Id("/abc"),
+            target: 987654321,
+            rename_to: "b.txt",
+        }),
+        example(&types::MoveFileArgs {
+            file: FileLocator::Id("/abc"),
+            target: 987654321,
+            move_to: FileLocator::Path("docs"),
+        }),
+        example(&types::ExecuteCommandArgs {
+            command: &["/help".into()],
+        }),
+        example(&types::ListAnnouncementArgs {
+            id: 987654321,
+            offset: 0,
+            size: Some(10),
+        }),
+        example(&types::AnnouncementArgs {
+            id: 987654321,
+            fid: "abc",
+        }),
+        example(&types::PublishAnnouncementArgs {
+            target: 987654321,
+            announcement: &announcement,
+        }),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::args;
+
+    fn json(type_name: &str) -> String {
+        args()
+            .into_iter()
+            .find(|example| example.type_name == type_name)
+            .unwrap()
+            .json
+    }
+
+    #[test]
+    fn every_example_serializes() {
+        assert!(args().iter().all(|example| example.json.starts_with('{')));
+    }
+
+    #[test]
+    fn send_message_flattens_contents() {
+        assert_eq!(
+            json("SendMessageArgs"),
+            r#"{"target":987654321,"quote":4321,"messageChain":[{"type":"At","target":123456789},{"type":"Plain","text":" hello"}]}"#,
+        );
+    }
+
+    #[test]
+    fn request_handling_uses_operate() {
+        assert_eq!(
+            json("HandleMemberJoinRequestArgs"),
+            r#"{"eventId":1,"fromId":123456789,"groupId":987654321,"operate":1,"message":"no"}"#,
+        );
+    }
+
+    #[test]
+    fn move_file_uses_move_to_path() {
+        assert_eq!(
+            json("MoveFileArgs"),
+            r#"{"id":"/abc","target":987654321,"moveToPath":"docs"}"#,
+        );
+    }
+}
@@ -1,23 +1,37 @@
 #![forbid(unsafe_code)]
 
 pub mod adapter;
+pub mod dispatch;
 pub mod event;
+pub mod examples;
 pub mod message;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod types;
+pub mod util;
 
 use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::future::{self, Future};
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroU32;
 use std::ops::Not;
+use std::pin::pin;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
 use derive_into_owned::IntoOwned;
+use futures_util::stream::{self, Stream, StreamExt as _, TryStreamExt as _};
 use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
 use types::{RoamingMessagesArgs, RoamingMessagesTarget};
 
 use self::adapter::{Bytes, MahSession};
-use self::message::{Message, OutgoingMessageContents, OutgoingMessageNode};
+use self::message::{AnyMessage as _, Message, OutgoingMessageContents, OutgoingMessageNode};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum MemberPermission {
     Member,
@@ -63,7 +77,8 @@ pub enum GroupHonor {
 #[derive(Clone, Debug, Deserialize)]
 pub struct Profile {
     pub nickname: String,
-    pub email: String,
+    #[serde(default)]
+    pub email: Option<String>,
     pub age: i32,
     pub level: i32,
     pub sign: String,
@@ -76,6 +91,31 @@ pub enum FileUpload {
     Bytes(Bytes),
 }
 
+/// The largest media uploads, in bytes, the server is expected to accept. See
+/// [`MahSession::media_limits`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MediaLimits {
+    pub image: usize,
+    pub voice: usize,
+    pub short_video: usize,
+}
+
+impl MediaLimits {
+    /// mirai-api-http does not report any limits. These are the sizes QQ is known to accept; the
+    /// server may still reject smaller files, e.g. in an unsupported format.
+    pub const DEFAULT: Self = Self {
+        image: 30 << 20,
+        voice: 10 << 20,
+        short_video: 100 << 20,
+    };
+}
+
+impl Default for MediaLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ImageInfo {
@@ -207,12 +247,17 @@ impl<'a> MemberInfoUpdate<'a> {
         }
     }
 
+    /// `None` leaves the special title unchanged; `Some("")` clears it.
     pub fn special_title(self, special_title: Option<impl Into<Cow<'a, str>>>) -> Self {
         Self {
             special_title: special_title.map(Into::into),
             ..self
         }
     }
+
+    pub fn clear_special_title(self) -> Self {
+        self.special_title(Some(""))
+    }
 }
 
 impl Default for MemberInfoUpdate<'_> {
@@ -221,6 +266,44 @@ impl Default for MemberInfoUpdate<'_> {
     }
 }
 
+#[derive(Clone, Debug, IntoOwned, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileUpdate<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sign: Option<Cow<'a, str>>,
+}
+
+impl<'a> ProfileUpdate<'a> {
+    pub fn new() -> Self {
+        Self {
+            nickname: None,
+            sign: None,
+        }
+    }
+
+    pub fn nickname(self, nickname: Option<impl Into<Cow<'a, str>>>) -> Self {
+        Self {
+            nickname: nickname.map(Into::into),
+            ..self
+        }
+    }
+
+    pub fn sign(self, sign: Option<impl Into<Cow<'a, str>>>) -> Self {
+        Self {
+            sign: sign.map(Into::into),
+            ..self
+        }
+    }
+}
+
+impl Default for ProfileUpdate<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Clone, Debug, IntoOwned, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Announcement<'a> {
@@ -411,39 +494,75 @@ pub trait GetProfile {
     async fn get_profile<S: MahSession + ?Sized>(&self, session: &S) -> Result<Profile, S::Error>;
 }
 
+// QQ numbers and group numbers are always positive, so anything else is most likely an id of the
+// wrong kind or an uninitialized value that would otherwise be sent to the wrong place.
+fn checked_id(kind: &str, id: i64) -> i64 {
+    debug_assert!(id > 0, "invalid {kind} id {id}");
+    id
+}
+
+/// Hands out typed handles for raw ids. The handles act as id newtypes: each only sends through
+/// the endpoint matching its kind, so e.g. a group id cannot end up in a friend message.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Bot;
 
 impl Bot {
     pub fn get_user(&self, id: i64) -> UserHandle {
-        UserHandle { id }
+        UserHandle {
+            id: checked_id("user", id),
+        }
     }
 
     pub fn get_friend(&self, id: i64) -> FriendHandle {
-        FriendHandle { id }
+        FriendHandle {
+            id: checked_id("friend", id),
+        }
     }
 
     pub fn get_stranger(&self, id: i64) -> StrangerHandle {
-        StrangerHandle { id }
+        StrangerHandle {
+            id: checked_id("stranger", id),
+        }
     }
 
     pub fn get_group(&self, id: i64) -> GroupHandle {
-        GroupHandle { id }
+        GroupHandle {
+            id: checked_id("group", id),
+        }
     }
 
     pub fn get_other_client(&self, id: i64) -> OtherClientHandle {
         OtherClientHandle { id }
     }
 
+    /// The returned handle does not know what kind of chat `context` refers to, so its
+    /// [`MessageHandle::reply_target`] is `None`. Use [`Bot::get_message_in`] when the kind is known.
     pub fn get_message(&self, id: i32, context: i64) -> MessageHandle {
-        MessageHandle { id, context }
+        MessageHandle {
+            id,
+            context,
+            target: None,
+        }
+    }
+
+    pub fn get_message_in(&self, id: i32, target: impl Into<ReplyTarget>) -> MessageHandle {
+        let target = target.into();
+        MessageHandle {
+            id,
+            context: match target {
+                ReplyTarget::Friend(friend) => friend.id,
+                ReplyTarget::Group(group) => group.id,
+                ReplyTarget::Member(member) => member.id,
+            },
+            target: Some(target),
+        }
     }
 
     pub async fn to_user<S: MahSession + ?Sized>(
         &self,
         session: &S,
     ) -> Result<UserDetails, S::Error> {
-        Ok(session.get_session_info().await?.qq)
+        session.cached_bot_user().await
     }
 
     pub async fn to_friend<S: MahSession + ?Sized>(
@@ -474,6 +593,43 @@ impl Bot {
         session.get_group_list().await
     }
 
+    /// Every friend and group the bot can send to, friends first. Uses [`ReplyTarget`] so the
+    /// result can be sent to uniformly; it never contains [`ReplyTarget::Member`].
+    pub async fn all_targets<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+    ) -> Result<Vec<ReplyTarget>, S::Error> {
+        let friends = self.get_friends(session).await?;
+        let groups = self.get_groups(session).await?;
+        Ok(friends
+            .iter()
+            .map(|friend| friend.handle().into())
+            .chain(groups.iter().map(|group| group.handle().into()))
+            .collect())
+    }
+
+    /// Issues one member list request per group the bot is in; cache the result if it is
+    /// needed often.
+    pub async fn shared_groups<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        user: UserHandle,
+    ) -> Result<Vec<MemberHandle>, S::Error> {
+        let mut members = Vec::new();
+        for group in self.get_groups(session).await? {
+            let group = group.handle();
+            if group
+                .get_members(session)
+                .await?
+                .iter()
+                .any(|member| member.id == user.id)
+            {
+                members.push(user.to_member(group));
+            }
+        }
+        Ok(members)
+    }
+
     pub async fn get_profile<S: MahSession + ?Sized>(
         &self,
         session: &S,
@@ -481,6 +637,16 @@ impl Bot {
         session.get_bot_profile().await
     }
 
+    /// No released version of mirai-api-http can change the bot's own profile, so with the HTTP
+    /// adapter this always fails with an unsupported error.
+    pub async fn set_profile<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        update: &ProfileUpdate<'_>,
+    ) -> Result<(), S::Error> {
+        session.update_bot_profile(update).await
+    }
+
     pub async fn execute_command<'a, S: MahSession + ?Sized>(
         &self,
         session: &S,
@@ -507,6 +673,9 @@ impl GetProfile for Bot {
     }
 }
 
+/// Common view of [`UserHandle`], [`FriendHandle`], [`StrangerHandle`] and [`MemberHandle`]. All
+/// of them refer to a user by id alone, so each can be converted into any other; no relationship
+/// with the bot is checked.
 pub trait AnyUserHandle {
     fn id(&self) -> i64;
 
@@ -530,7 +699,62 @@ pub trait AnyUserHandle {
     }
 
     fn avatar_url(&self) -> String {
-        format!("https://q1.qlogo.cn/g?b=qq&nk={}&s=640", self.id())
+        self.avatar_url_with(&AvatarHosts::DEFAULT)
+    }
+
+    fn avatar_url_with(&self, hosts: &AvatarHosts) -> String {
+        format!("{}/g?b=qq&nk={}&s=640", hosts.user, self.id())
+    }
+}
+
+/// The origins avatar urls point to, e.g. a mirror for networks where the Tencent hosts are
+/// blocked. Origins are given without a trailing slash; the paths are kept unchanged.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AvatarHosts<'a> {
+    pub user: Cow<'a, str>,
+    pub group: Cow<'a, str>,
+}
+
+impl AvatarHosts<'_> {
+    pub const DEFAULT: AvatarHosts<'static> = AvatarHosts {
+        user: Cow::Borrowed("https://q1.qlogo.cn"),
+        group: Cow::Borrowed("https://p.qlogo.cn"),
+    };
+}
+
+impl<'a> AvatarHosts<'a> {
+    pub fn new() -> Self {
+        Self::DEFAULT
+    }
+
+    pub fn user(self, user: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            user: user.into(),
+            ..self
+        }
+    }
+
+    pub fn group(self, group: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            group: group.into(),
+            ..self
+        }
+    }
+}
+
+impl Default for AvatarHosts<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub trait Contact {
+    fn id(&self) -> i64;
+    fn display_name(&self) -> &str;
+    fn avatar_url_with(&self, hosts: &AvatarHosts) -> String;
+
+    fn avatar_url(&self) -> String {
+        self.avatar_url_with(&AvatarHosts::DEFAULT)
     }
 }
 
@@ -540,6 +764,10 @@ pub struct UserHandle {
 }
 
 impl UserHandle {
+    pub fn from_id(id: i64) -> Self {
+        Bot.get_user(id)
+    }
+
     pub async fn get_profile<S: MahSession + ?Sized>(
         &self,
         session: &S,
@@ -556,6 +784,24 @@ impl AnyUserHandle for UserHandle {
     }
 }
 
+impl From<FriendHandle> for UserHandle {
+    fn from(value: FriendHandle) -> Self {
+        value.to_user()
+    }
+}
+
+impl From<StrangerHandle> for UserHandle {
+    fn from(value: StrangerHandle) -> Self {
+        value.to_user()
+    }
+}
+
+impl From<MemberHandle> for UserHandle {
+    fn from(value: MemberHandle) -> Self {
+        value.to_user()
+    }
+}
+
 #[async_trait]
 impl GetProfile for UserHandle {
     async fn get_profile<S: MahSession + ?Sized>(&self, session: &S) -> Result<Profile, S::Error> {
@@ -576,12 +822,34 @@ impl UserDetails {
     }
 }
 
+impl Contact for UserDetails {
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn display_name(&self) -> &str {
+        if self.remark.is_empty() {
+            &self.nickname
+        } else {
+            &self.remark
+        }
+    }
+
+    fn avatar_url_with(&self, hosts: &AvatarHosts) -> String {
+        self.handle().avatar_url_with(hosts)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct FriendHandle {
     id: i64,
 }
 
 impl FriendHandle {
+    pub fn from_id(id: i64) -> Self {
+        Bot.get_friend(id)
+    }
+
     pub async fn send_message<'a, S: MahSession + ?Sized>(
         &self,
         session: &S,
@@ -590,11 +858,43 @@ impl FriendHandle {
         Ok(MessageHandle {
             id: session
                 .send_friend_message(&types::SendMessageArgs {
-                    target: self.id,
+                    target: checked_id("friend", self.id),
                     contents: message,
                 })
                 .await?,
             context: self.id,
+            target: Some((*self).into()),
+        })
+    }
+
+    pub async fn send_message_detailed<'a, S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        message: &'a OutgoingMessageContents<'a>,
+    ) -> Result<SentMessage, S::Error> {
+        let details = session
+            .send_friend_message_detailed(&types::SendMessageArgs {
+                target: checked_id("friend", self.id),
+                contents: message,
+            })
+            .await?;
+        Ok(SentMessage::new(details, (*self).into()))
+    }
+
+    pub async fn send_raw_chain<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        chain: &serde_json::Value,
+    ) -> Result<MessageHandle, S::Error> {
+        Ok(MessageHandle {
+            id: session
+                .send_raw_friend_message(&types::SendRawMessageArgs {
+                    target: checked_id("friend", self.id),
+                    message_chain: chain,
+                })
+                .await?,
+            context: self.id,
+            target: Some((*self).into()),
         })
     }
 
@@ -752,12 +1052,30 @@ impl FriendDetails {
     }
 }
 
+impl Contact for FriendDetails {
+    fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    fn display_name(&self) -> &str {
+        self.0.display_name()
+    }
+
+    fn avatar_url_with(&self, hosts: &AvatarHosts) -> String {
+        self.0.avatar_url_with(hosts)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct StrangerHandle {
     id: i64,
 }
 
 impl StrangerHandle {
+    pub fn from_id(id: i64) -> Self {
+        Bot.get_stranger(id)
+    }
+
     pub async fn send_nudge<S: MahSession + ?Sized>(
         &self,
         session: &S,
@@ -813,18 +1131,39 @@ impl StrangerDetails {
     }
 }
 
+impl Contact for StrangerDetails {
+    fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    fn display_name(&self) -> &str {
+        self.0.display_name()
+    }
+
+    fn avatar_url_with(&self, hosts: &AvatarHosts) -> String {
+        self.0.avatar_url_with(hosts)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct GroupHandle {
     id: i64,
 }
 
 impl GroupHandle {
+    pub fn from_id(id: i64) -> Self {
+        Bot.get_group(id)
+    }
+
     pub fn id(&self) -> i64 {
         self.id
     }
 
     pub fn get_member(&self, id: i64) -> MemberHandle {
-        MemberHandle { id, group: *self }
+        MemberHandle {
+            id: checked_id("member", id),
+            group: *self,
+        }
     }
 
     pub fn get_file(&self, id: String) -> FileHandle {
@@ -849,6 +1188,64 @@ impl GroupHandle {
             .await
     }
 
+    pub async fn get_members_sorted<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        sort_key: MemberSortKey,
+    ) -> Result<Vec<MemberDetails>, S::Error> {
+        let mut members = self.get_members(session).await?;
+        members.sort_by(|a, b| sort_key.compare(a, b));
+        Ok(members)
+    }
+
+    pub async fn member_count<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+    ) -> Result<usize, S::Error> {
+        // the member list excludes the bot itself; mirai does not expose the group's capacity
+        Ok(self.get_members(session).await?.len() + 1)
+    }
+
+    /// Members with [`MemberPermission::Admin`], not including the owner. Like the member list,
+    /// this never includes the bot itself.
+    pub async fn admins<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+    ) -> Result<Vec<MemberDetails>, S::Error> {
+        let mut members = self.get_members(session).await?;
+        members.retain(|member| member.permission == MemberPermission::Admin);
+        Ok(members)
+    }
+
+    /// Returns `None` if the bot itself owns the group, since the member list excludes the bot.
+    pub async fn owner<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+    ) -> Result<Option<MemberDetails>, S::Error> {
+        Ok(self
+            .get_members(session)
+            .await?
+            .into_iter()
+            .find(|member| member.permission == MemberPermission::Owner))
+    }
+
+    pub async fn inactive_members<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        since: Duration,
+    ) -> Result<Vec<MemberDetails>, S::Error> {
+        let threshold = SystemTime::now().checked_sub(since);
+        let mut members = self.get_members(session).await?;
+        members.retain(|member| {
+            member.last_speak_time_secs == 0
+                || member
+                    .last_speak_time()
+                    .zip(threshold)
+                    .is_some_and(|(time, threshold)| time < threshold)
+        });
+        Ok(members)
+    }
+
     pub async fn refresh_members<S: MahSession + ?Sized>(
         &self,
         session: &S,
@@ -870,11 +1267,43 @@ impl GroupHandle {
         Ok(MessageHandle {
             id: session
                 .send_group_message(&types::SendMessageArgs {
-                    target: self.id,
+                    target: checked_id("group", self.id),
                     contents: message,
                 })
                 .await?,
             context: self.id,
+            target: Some((*self).into()),
+        })
+    }
+
+    pub async fn send_message_detailed<'a, S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        message: &'a OutgoingMessageContents<'a>,
+    ) -> Result<SentMessage, S::Error> {
+        let details = session
+            .send_group_message_detailed(&types::SendMessageArgs {
+                target: checked_id("group", self.id),
+                contents: message,
+            })
+            .await?;
+        Ok(SentMessage::new(details, (*self).into()))
+    }
+
+    pub async fn send_raw_chain<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        chain: &serde_json::Value,
+    ) -> Result<MessageHandle, S::Error> {
+        Ok(MessageHandle {
+            id: session
+                .send_raw_group_message(&types::SendRawMessageArgs {
+                    target: checked_id("group", self.id),
+                    message_chain: chain,
+                })
+                .await?,
+            context: self.id,
+            target: Some((*self).into()),
         })
     }
 
@@ -946,10 +1375,35 @@ impl GroupHandle {
             .await
     }
 
+    pub async fn set_mute_all<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        mute_all: bool,
+    ) -> Result<GroupConfig, S::Error> {
+        if mute_all {
+            self.mute_all(session).await?;
+        } else {
+            self.unmute_all(session).await?;
+        }
+        self.get_group_config(session).await
+    }
+
     pub async fn quit<S: MahSession + ?Sized>(&self, session: &S) -> Result<(), S::Error> {
         session.quit(&types::TargetArgs { target: self.id }).await
     }
 
+    /// Whether the bot is an admin or the owner of the group, which @all requires. This does not
+    /// account for the daily @all quota, which mirai does not expose: a message can still fail
+    /// with [`SendMessageFailure::AtAllLimited`](adapter::SendMessageFailure::AtAllLimited) when
+    /// this returns `true`.
+    pub async fn can_at_all<S: MahSession + ?Sized>(&self, session: &S) -> Result<bool, S::Error> {
+        Ok(session
+            .get_group_list()
+            .await?
+            .iter()
+            .any(|group| group.id == self.id && group.permission != MemberPermission::Member))
+    }
+
     pub async fn get_group_config<S: MahSession + ?Sized>(
         &self,
         session: &S,
@@ -975,13 +1429,13 @@ impl GroupHandle {
     pub async fn list_files<S: MahSession + ?Sized>(
         &self,
         session: &S,
-        path: Option<&str>,
+        directory: impl Into<FileAddress<'_>>,
         range: (i32, Option<i32>),
         download: bool,
     ) -> Result<Vec<FileDetails>, S::Error> {
         session
             .list_file(&types::ListFileArgs {
-                directory: path.map_or_else(types::FileLocator::root, types::FileLocator::Path),
+                directory: directory.into().into(),
                 target: self.id,
                 offset: range.0,
                 size: range.1,
@@ -993,12 +1447,12 @@ impl GroupHandle {
     pub async fn get_file_info<S: MahSession + ?Sized>(
         &self,
         session: &S,
-        path: &str,
+        file: impl Into<FileAddress<'_>>,
         download: bool,
     ) -> Result<FileDetails, S::Error> {
         session
             .get_file_info(&types::GetFileInfoArgs {
-                file: types::FileLocator::Path(path),
+                file: file.into().into(),
                 target: self.id,
                 with_download_info: download,
             })
@@ -1008,12 +1462,12 @@ impl GroupHandle {
     pub async fn make_directory<S: MahSession + ?Sized>(
         &self,
         session: &S,
-        path: Option<&str>,
+        parent: impl Into<FileAddress<'_>>,
         name: &str,
     ) -> Result<FileDetails, S::Error> {
         session
             .mk_dir(&types::MkDirArgs {
-                directory: path.map_or_else(types::FileLocator::root, types::FileLocator::Path),
+                directory: parent.into().into(),
                 target: self.id,
                 directory_name: name,
             })
@@ -1035,11 +1489,11 @@ impl GroupHandle {
     pub async fn delete_file<S: MahSession + ?Sized>(
         &self,
         session: &S,
-        path: &str,
+        file: impl Into<FileAddress<'_>>,
     ) -> Result<(), S::Error> {
         session
             .delete_file(&types::FileArgs {
-                file: types::FileLocator::Path(path),
+                file: file.into().into(),
                 target: self.id,
             })
             .await
@@ -1048,42 +1502,37 @@ impl GroupHandle {
     pub async fn move_file<S: MahSession + ?Sized>(
         &self,
         session: &S,
-        path: &str,
-        new_parent: &FileHandle,
+        file: impl Into<FileAddress<'_>>,
+        new_parent: impl Into<FileAddress<'_>>,
     ) -> Result<(), S::Error> {
         session
             .move_file(&types::MoveFileArgs {
-                file: types::FileLocator::Path(path),
+                file: file.into().into(),
                 target: self.id,
-                move_to: types::FileLocator::Id(&new_parent.id),
+                move_to: new_parent.into().into(),
             })
             .await
     }
 
+    #[deprecated = "use `move_file` with a `FileAddress::Path`"]
     pub async fn move_file_to_path<S: MahSession + ?Sized>(
         &self,
         session: &S,
         path: &str,
         new_parent_path: &str,
     ) -> Result<(), S::Error> {
-        session
-            .move_file(&types::MoveFileArgs {
-                file: types::FileLocator::Path(path),
-                target: self.id,
-                move_to: types::FileLocator::Path(new_parent_path),
-            })
-            .await
+        self.move_file(session, path, new_parent_path).await
     }
 
     pub async fn rename_file<S: MahSession + ?Sized>(
         &self,
         session: &S,
-        path: &str,
+        file: impl Into<FileAddress<'_>>,
         new_name: &str,
     ) -> Result<(), S::Error> {
         session
             .rename_file(&types::RenameFileArgs {
-                file: types::FileLocator::Path(path),
+                file: file.into().into(),
                 target: self.id,
                 rename_to: new_name,
             })
@@ -1104,6 +1553,39 @@ impl GroupHandle {
             .await
     }
 
+    /// Walks every announcement, newest first, fetching 10 at a time.
+    pub fn list_announcements_stream<'a, S: MahSession + ?Sized>(
+        &self,
+        session: &'a S,
+    ) -> impl Stream<Item = Result<AnnouncementDetails, S::Error>> + 'a {
+        self.list_announcements_stream_sized(session, NonZeroU32::new(10).unwrap())
+    }
+
+    pub fn list_announcements_stream_sized<'a, S: MahSession + ?Sized>(
+        &self,
+        session: &'a S,
+        page_size: NonZeroU32,
+    ) -> impl Stream<Item = Result<AnnouncementDetails, S::Error>> + 'a {
+        let page_size = i32::try_from(page_size.get()).unwrap_or(i32::MAX);
+        let group = *self;
+        stream::try_unfold(Some(0), move |offset| async move {
+            let Some(offset) = offset else {
+                return Ok::<_, S::Error>(None);
+            };
+            let page = group
+                .list_announcements(session, (offset, Some(page_size)))
+                .await?;
+            if page.is_empty() {
+                return Ok(None);
+            }
+            let next = offset
+                .checked_add(page_size)
+                .filter(|_| page.len() >= page_size as usize);
+            Ok(Some((stream::iter(page.into_iter().map(Ok)), next)))
+        })
+        .try_flatten()
+    }
+
     pub async fn publish_announcement<'a, S: MahSession + ?Sized>(
         &self,
         session: &S,
@@ -1116,6 +1598,32 @@ impl GroupHandle {
             })
             .await
     }
+
+    pub async fn set_entrance_announcement<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        contents: impl Into<Cow<'_, str>>,
+    ) -> Result<AnnouncementDetails, S::Error> {
+        let announcement = Announcement::new(contents)
+            .send_to_new_member(true)
+            .pinned(true);
+        self.publish_announcement(session, &announcement).await
+    }
+
+    /// The most recent announcement published by the bot. mirai does not report announcement
+    /// flags, so this is the one set by [`GroupHandle::set_entrance_announcement`] only if the bot
+    /// has published nothing since.
+    pub async fn latest_bot_announcement<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+    ) -> Result<Option<AnnouncementDetails>, S::Error> {
+        let bot = session.cached_bot_user().await?.id;
+        // mirai lists announcements newest first, so the first match is the latest
+        let mut announcements = pin!(self
+            .list_announcements_stream(session)
+            .try_filter(|announcement| future::ready(announcement.publisher_id == bot)));
+        announcements.try_next().await
+    }
 }
 
 #[async_trait]
@@ -1191,6 +1699,20 @@ impl GroupDetails {
     }
 }
 
+impl Contact for GroupDetails {
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn display_name(&self) -> &str {
+        &self.name
+    }
+
+    fn avatar_url_with(&self, hosts: &AvatarHosts) -> String {
+        format!("{0}/gh/{1}/{1}/640", hosts.group, self.id)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct MemberHandle {
     id: i64,
@@ -1214,6 +1736,13 @@ impl MemberHandle {
             .await
     }
 
+    pub async fn resolve_details<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+    ) -> Result<MemberDetails, S::Error> {
+        Ok(self.resolve(session).await?.details)
+    }
+
     pub async fn send_message<'a, S: MahSession + ?Sized>(
         &self,
         session: &S,
@@ -1222,12 +1751,46 @@ impl MemberHandle {
         Ok(MessageHandle {
             id: session
                 .send_temp_message(&types::SendTempMessageArgs {
-                    qq: self.id,
-                    group: self.group.id,
+                    qq: checked_id("member", self.id),
+                    group: checked_id("group", self.group.id),
                     contents: message,
                 })
                 .await?,
             context: self.id,
+            target: Some((*self).into()),
+        })
+    }
+
+    pub async fn send_message_detailed<'a, S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        message: &'a OutgoingMessageContents<'a>,
+    ) -> Result<SentMessage, S::Error> {
+        let details = session
+            .send_temp_message_detailed(&types::SendTempMessageArgs {
+                qq: checked_id("member", self.id),
+                group: checked_id("group", self.group.id),
+                contents: message,
+            })
+            .await?;
+        Ok(SentMessage::new(details, (*self).into()))
+    }
+
+    pub async fn send_raw_chain<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        chain: &serde_json::Value,
+    ) -> Result<MessageHandle, S::Error> {
+        Ok(MessageHandle {
+            id: session
+                .send_raw_temp_message(&types::SendRawTempMessageArgs {
+                    qq: checked_id("member", self.id),
+                    group: checked_id("group", self.group.id),
+                    message_chain: chain,
+                })
+                .await?,
+            context: self.id,
+            target: Some((*self).into()),
         })
     }
 
@@ -1414,6 +1977,58 @@ impl MemberDetails {
     pub fn mute_time_remaining(&self) -> Duration {
         Duration::from_secs(self.mute_time_remaining_secs as u64)
     }
+
+    /// The remaining mute time formatted like `2h 30m`, or `0s` if not muted.
+    pub fn mute_time_remaining_display(&self) -> String {
+        util::format_duration(self.mute_time_remaining())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemberSortKey {
+    /// Owner first, then admins, then members, each by join time.
+    Permission,
+    /// Earliest joined first.
+    JoinTime,
+    /// Most recently active first. Members who never spoke, whose timestamp is zero, come last.
+    LastSpeak,
+    /// By member name.
+    Name,
+}
+
+impl MemberSortKey {
+    /// Ties are broken by id so the order is stable across fetches.
+    pub fn compare(self, a: &MemberDetails, b: &MemberDetails) -> Ordering {
+        let ordering = match self {
+            Self::Permission => b
+                .permission
+                .cmp(&a.permission)
+                .then(a.join_time_secs.cmp(&b.join_time_secs)),
+            Self::JoinTime => a.join_time_secs.cmp(&b.join_time_secs),
+            Self::LastSpeak => {
+                let never_spoke = |member: &MemberDetails| member.last_speak_time_secs == 0;
+                never_spoke(a)
+                    .cmp(&never_spoke(b))
+                    .then(b.last_speak_time_secs.cmp(&a.last_speak_time_secs))
+            }
+            Self::Name => a.member_name.cmp(&b.member_name),
+        };
+        ordering.then(a.id.cmp(&b.id))
+    }
+}
+
+impl Contact for MemberDetails {
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn display_name(&self) -> &str {
+        &self.member_name
+    }
+
+    fn avatar_url_with(&self, hosts: &AvatarHosts) -> String {
+        self.handle().avatar_url_with(hosts)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -1424,6 +2039,167 @@ pub struct MemberInfo {
     pub activity: MemberActivity,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReplyTarget {
+    Friend(FriendHandle),
+    Group(GroupHandle),
+    Member(MemberHandle),
+}
+
+impl ReplyTarget {
+    pub async fn send_message<'a, S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        message: &'a OutgoingMessageContents<'a>,
+    ) -> Result<MessageHandle, S::Error> {
+        match self {
+            Self::Friend(friend) => friend.send_message(session, message).await,
+            Self::Group(group) => group.send_message(session, message).await,
+            Self::Member(member) => member.send_message(session, message).await,
+        }
+    }
+
+    pub async fn upload_image<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        image: FileUpload,
+    ) -> Result<ImageInfo, S::Error> {
+        match self {
+            Self::Friend(friend) => friend.upload_image(session, image).await,
+            Self::Group(group) => group.upload_image(session, image).await,
+            Self::Member(member) => member.upload_image(session, image).await,
+        }
+    }
+
+    pub async fn upload_voice<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        voice: FileUpload,
+    ) -> Result<VoiceInfo, S::Error> {
+        match self {
+            Self::Friend(friend) => friend.upload_voice(session, voice).await,
+            Self::Group(group) => group.upload_voice(session, voice).await,
+            Self::Member(member) => member.upload_voice(session, voice).await,
+        }
+    }
+
+    pub async fn upload_short_video<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        video: Bytes,
+        thumbnail: Bytes,
+    ) -> Result<ShortVideoInfo, S::Error> {
+        match self {
+            Self::Friend(friend) => friend.upload_short_video(session, video, thumbnail).await,
+            Self::Group(group) => group.upload_short_video(session, video, thumbnail).await,
+            Self::Member(member) => member.upload_short_video(session, video, thumbnail).await,
+        }
+    }
+}
+
+#[async_trait]
+impl SendMessage for ReplyTarget {
+    async fn send_message<'a, S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        message: &'a OutgoingMessageContents<'a>,
+    ) -> Result<MessageHandle, S::Error> {
+        self.send_message(session, message).await
+    }
+
+    async fn upload_image<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        image: FileUpload,
+    ) -> Result<ImageInfo, S::Error> {
+        self.upload_image(session, image).await
+    }
+
+    async fn upload_voice<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        voice: FileUpload,
+    ) -> Result<VoiceInfo, S::Error> {
+        self.upload_voice(session, voice).await
+    }
+
+    async fn upload_short_video<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        video: Bytes,
+        thumbnail: Bytes,
+    ) -> Result<ShortVideoInfo, S::Error> {
+        self.upload_short_video(session, video, thumbnail).await
+    }
+}
+
+impl From<FriendHandle> for ReplyTarget {
+    fn from(value: FriendHandle) -> Self {
+        Self::Friend(value)
+    }
+}
+
+impl From<GroupHandle> for ReplyTarget {
+    fn from(value: GroupHandle) -> Self {
+        Self::Group(value)
+    }
+}
+
+impl From<MemberHandle> for ReplyTarget {
+    fn from(value: MemberHandle) -> Self {
+        Self::Member(value)
+    }
+}
+
+/// Refers to a group file or directory either by its id or by its path. The empty id refers to the
+/// root directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FileAddress<'a> {
+    Id(&'a str),
+    Path(&'a str),
+}
+
+impl FileAddress<'_> {
+    pub fn root() -> Self {
+        Self::Id("")
+    }
+}
+
+/// Plain strings are paths, as they were before files could also be addressed by id.
+impl<'a> From<&'a str> for FileAddress<'a> {
+    fn from(path: &'a str) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl<'a> From<&'a String> for FileAddress<'a> {
+    fn from(path: &'a String) -> Self {
+        Self::Path(path)
+    }
+}
+
+/// `None` is the root directory.
+impl<'a> From<Option<&'a str>> for FileAddress<'a> {
+    fn from(path: Option<&'a str>) -> Self {
+        path.map_or_else(Self::root, Self::Path)
+    }
+}
+
+impl<'a> From<&'a FileHandle> for FileAddress<'a> {
+    fn from(file: &'a FileHandle) -> Self {
+        Self::Id(&file.id)
+    }
+}
+
+impl<'a> From<FileAddress<'a>> for types::FileLocator<'a> {
+    fn from(address: FileAddress<'a>) -> Self {
+        match address {
+            FileAddress::Id(id) => Self::Id(id),
+            FileAddress::Path(path) => Self::Path(path),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct FileHandle {
     id: String,
@@ -1482,29 +2258,24 @@ impl FileHandle {
     pub async fn move_<S: MahSession + ?Sized>(
         &self,
         session: &S,
-        new_parent: &FileHandle,
+        new_parent: impl Into<FileAddress<'_>>,
     ) -> Result<(), S::Error> {
         session
             .move_file(&types::MoveFileArgs {
                 file: types::FileLocator::Id(&self.id),
                 target: self.group.id,
-                move_to: types::FileLocator::Id(&new_parent.id),
+                move_to: new_parent.into().into(),
             })
             .await
     }
 
+    #[deprecated = "use `move_` with a `FileAddress::Path`"]
     pub async fn move_to_path<S: MahSession + ?Sized>(
         &self,
         session: &S,
         new_parent_path: &str,
     ) -> Result<(), S::Error> {
-        session
-            .move_file(&types::MoveFileArgs {
-                file: types::FileLocator::Id(&self.id),
-                target: self.group.id,
-                move_to: types::FileLocator::Path(new_parent_path),
-            })
-            .await
+        self.move_(session, new_parent_path).await
     }
 
     pub async fn rename<S: MahSession + ?Sized>(
@@ -1520,6 +2291,93 @@ impl FileHandle {
             })
             .await
     }
+
+    /// Uploads a copy of this file, under the same name, to the directory `path` of another group.
+    /// `fetch` opens the original at its url as a stream, e.g. with the adapter's own download
+    /// helper, which is passed on to the upload as it arrives. Files larger than `max_bytes` are
+    /// not copied, and a copy whose MD5 does not match the original is deleted again.
+    pub async fn copy_to<S, Fut, St>(
+        &self,
+        session: &S,
+        group: GroupHandle,
+        path: &str,
+        max_bytes: usize,
+        fetch: impl FnOnce(String) -> Fut,
+    ) -> Result<FileDetails, CopyFileError<S::Error>>
+    where
+        S: MahSession + ?Sized,
+        Fut: Future<Output = Result<St, S::Error>>,
+        St: Stream<Item = Result<Bytes, S::Error>> + Send + 'static,
+    {
+        let source = self.resolve(session, true).await?;
+        let metadata = source.metadata.ok_or(CopyFileError::Directory)?;
+        let url = metadata.download_info.ok_or(CopyFileError::MissingUrl)?.url;
+        if metadata.size > max_bytes as i64 {
+            return Err(CopyFileError::TooLarge(max_bytes));
+        }
+        // The stream ends early rather than failing when the original turns out larger than its
+        // reported size, since only the session can make its errors.
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let file = fetch(url).await?.scan(0, {
+            let exceeded = exceeded.clone();
+            move |received, chunk| {
+                if let Ok(chunk) = &chunk {
+                    *received += chunk.len();
+                    if *received > max_bytes {
+                        exceeded.store(true, atomic::Ordering::Relaxed);
+                        return future::ready(None);
+                    }
+                }
+                future::ready(Some(chunk))
+            }
+        });
+        let copy = session
+            .upload_file_stream(
+                group.id,
+                path.to_owned().into(),
+                source.name.into(),
+                metadata.size as u64,
+                Box::pin(file),
+            )
+            .await;
+        if exceeded.load(atomic::Ordering::Relaxed) {
+            if let Ok(copy) = copy {
+                copy.handle()
+                    .delete(session)
+                    .await
+                    .map_err(CopyFileError::CorruptCopyLeft)?;
+            }
+            return Err(CopyFileError::TooLarge(max_bytes));
+        }
+        let copy = copy?.handle().resolve(session, false).await?;
+        let md5 = copy.metadata.as_ref().map(|copy| &copy.md5);
+        if !md5.is_some_and(|md5| md5.eq_ignore_ascii_case(&metadata.md5)) {
+            copy.handle()
+                .delete(session)
+                .await
+                .map_err(CopyFileError::CorruptCopyLeft)?;
+            return Err(CopyFileError::ChecksumMismatch);
+        }
+        Ok(copy)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Error)]
+pub enum CopyFileError<E> {
+    #[error("directories cannot be copied")]
+    Directory,
+    #[error("file has no download url")]
+    MissingUrl,
+    #[error("file exceeds {0} bytes")]
+    TooLarge(usize),
+    #[error("copied file does not match the original")]
+    ChecksumMismatch,
+    /// The copy is incomplete or did not match the original and is still in the group, since
+    /// deleting it failed.
+    #[error("copied file does not match the original and could not be deleted: {0}")]
+    CorruptCopyLeft(E),
+    #[error(transparent)]
+    Session(#[from] E),
 }
 
 fn deserialize_file_metadata<'de, D: Deserializer<'de>>(
@@ -1626,6 +2484,23 @@ impl AnnouncementHandle {
             })
             .await
     }
+
+    /// mirai cannot edit announcements, so this publishes a new one and then deletes this one,
+    /// returning the handle of the new one. Members are notified of it again, and confirmations
+    /// of the old one are lost. If publishing fails the old announcement is kept; if deleting
+    /// fails both remain.
+    pub async fn replace<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        announcement: &Announcement<'_>,
+    ) -> Result<AnnouncementHandle, S::Error> {
+        let new = self
+            .group
+            .publish_announcement(session, announcement)
+            .await?;
+        self.delete(session).await?;
+        Ok(new.handle())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -1653,6 +2528,21 @@ impl AnnouncementDetails {
     pub fn publication_time(&self) -> Option<SystemTime> {
         SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(self.publication_time_secs as u64))
     }
+
+    /// How long ago the announcement was published, formatted like `3d 4h`.
+    pub fn age_display(&self) -> Option<String> {
+        let age = self.publication_time()?.elapsed().unwrap_or_default();
+        Some(util::format_duration(age))
+    }
+
+    pub fn pending_confirmations(&self, members: &[MemberDetails]) -> usize {
+        if self.all_confirmed {
+            return 0;
+        }
+        members
+            .len()
+            .saturating_sub(self.confirmed_count.max(0) as usize)
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -1661,9 +2551,30 @@ pub struct OtherClientHandle {
 }
 
 impl OtherClientHandle {
+    pub fn from_id(id: i64) -> Self {
+        Bot.get_other_client(id)
+    }
+
     pub fn id(&self) -> i64 {
         self.id
     }
+
+    // mirai has no upload type for other clients; friend uploads are accepted when sending to them
+    pub async fn upload_image<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        image: FileUpload,
+    ) -> Result<ImageInfo, S::Error> {
+        session.upload_image(types::MediaType::Friend, image).await
+    }
+
+    pub async fn upload_voice<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        voice: FileUpload,
+    ) -> Result<VoiceInfo, S::Error> {
+        session.upload_voice(types::MediaType::Friend, voice).await
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -1676,12 +2587,81 @@ impl OtherClientDetails {
     pub fn handle(&self) -> OtherClientHandle {
         OtherClientHandle { id: self.id }
     }
+
+    pub fn client_platform(&self) -> ClientPlatform {
+        self.platform.as_str().into()
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ClientPlatform {
+    AndroidPhone,
+    AndroidPad,
+    AndroidWatch,
+    Ios,
+    Ipad,
+    Mobile,
+    Windows,
+    MacOs,
+    Other(String),
+}
+
+impl From<&str> for ClientPlatform {
+    fn from(value: &str) -> Self {
+        match value {
+            "ANDROID_PHONE" => Self::AndroidPhone,
+            "ANDROID_PAD" => Self::AndroidPad,
+            "ANDROID_WATCH" => Self::AndroidWatch,
+            "IOS" => Self::Ios,
+            "IPAD" => Self::Ipad,
+            "MOBILE" => Self::Mobile,
+            "WINDOWS" => Self::Windows,
+            "MACOS" => Self::MacOs,
+            _ => Self::Other(value.to_owned()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SentMessage {
+    pub handle: MessageHandle,
+    /// The send time reported in the response, if any. mirai-api-http currently only returns the
+    /// message id, so expect `None` there rather than a time taken on this side.
+    pub time: Option<SystemTime>,
+}
+
+impl SentMessage {
+    fn new(details: types::SendMessageDetails, target: ReplyTarget) -> Self {
+        Self {
+            handle: Bot.get_message_in(details.message_id, target),
+            time: details.time_secs.and_then(|secs| {
+                SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
 pub struct MessageHandle {
     id: i32,
     context: i64,
+    target: Option<ReplyTarget>,
+}
+
+// Handles to the same message compare equal whether or not the kind of context is known.
+impl PartialEq for MessageHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.context == other.context
+    }
+}
+
+impl Eq for MessageHandle {}
+
+impl Hash for MessageHandle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.context.hash(state);
+    }
 }
 
 impl MessageHandle {
@@ -1693,6 +2673,10 @@ impl MessageHandle {
         self.context
     }
 
+    pub fn reply_target(&self) -> Option<ReplyTarget> {
+        self.target
+    }
+
     pub async fn resolve<S: MahSession + ?Sized>(&self, session: &S) -> Result<Message, S::Error> {
         session
             .get_message_from_id(&types::MessageIdArgs {
@@ -1702,6 +2686,52 @@ impl MessageHandle {
             .await
     }
 
+    /// This message with up to `before` earlier and `after` later roaming messages, oldest first.
+    pub async fn context_window<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        before: usize,
+        after: usize,
+    ) -> Result<Vec<Message>, S::Error> {
+        const MIN_SPAN_SECS: i64 = 10 * 60;
+        const MAX_SPAN_SECS: i64 = 24 * 60 * 60;
+        let message = self.resolve(session).await?;
+        let target = match self.target.or_else(|| message.reply_target()) {
+            Some(ReplyTarget::Friend(friend)) => RoamingMessagesTarget::Friend(friend.id),
+            Some(ReplyTarget::Group(group)) => RoamingMessagesTarget::Group(group.id),
+            _ => return Ok(vec![message]),
+        };
+        let Some(time) = message.time_secs().map(i64::from) else {
+            return Ok(vec![message]);
+        };
+        let mut span = MIN_SPAN_SECS;
+        loop {
+            let mut messages = session
+                .roaming_messages(&RoamingMessagesArgs {
+                    time_start: time - span,
+                    time_end: time + span,
+                    target,
+                })
+                .await?;
+            messages.sort_by_key(|message| message.time_secs());
+            let index = messages
+                .iter()
+                .position(|message| message.id() == Some(self.id));
+            if let Some(index) = index {
+                let enough = index >= before && messages.len() - index > after;
+                if enough || span >= MAX_SPAN_SECS {
+                    let end = messages.len().min(index + after + 1);
+                    messages.truncate(end);
+                    messages.drain(..index.saturating_sub(before));
+                    return Ok(messages);
+                }
+            } else if span >= MAX_SPAN_SECS {
+                return Ok(vec![message]);
+            }
+            span = (span * 2).min(MAX_SPAN_SECS);
+        }
+    }
+
     pub async fn recall<S: MahSession + ?Sized>(&self, session: &S) -> Result<(), S::Error> {
         session
             .recall(&types::MessageIdArgs {
@@ -1719,9 +2749,54 @@ impl MessageHandle {
             })
             .await
     }
+
+    pub async fn react<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        face_id: i32,
+    ) -> Result<(), S::Error> {
+        session
+            .react(&types::ReactionArgs {
+                target: self.context,
+                message_id: self.id,
+                face_id,
+            })
+            .await
+    }
+
+    pub async fn unreact<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        face_id: i32,
+    ) -> Result<(), S::Error> {
+        session
+            .unreact(&types::ReactionArgs {
+                target: self.context,
+                message_id: self.id,
+                face_id,
+            })
+            .await
+    }
 }
 
 #[doc(hidden)]
 pub mod __ {
     pub use std::convert::Into;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "invalid group id -1"))]
+    fn negative_group_id_is_caught_in_debug_builds() {
+        Bot.get_group(-1);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "invalid member id 0"))]
+    fn zero_member_id_is_caught_in_debug_builds() {
+        Bot.get_group(987654321).get_member(0);
+    }
+}
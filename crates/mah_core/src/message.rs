@@ -1,15 +1,25 @@
 use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fmt;
-use std::time::{Duration, SystemTime};
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+use std::pin::pin;
+use std::time::{Duration, Instant, SystemTime};
 
 use derive_into_owned::IntoOwned;
 use enum_dispatch::enum_dispatch;
+use futures_util::stream::{Stream, StreamExt as _};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use strum_macros::EnumDiscriminants;
 use thiserror::Error;
 
+use crate::adapter::{Bytes, MahSession};
 use crate::{
-    types, Bot, FileHandle, FriendDetails, GroupDetails, GroupHandle, MemberDetails, MemberHandle,
-    MessageHandle, OtherClientDetails, StrangerDetails, UserHandle,
+    types, AnyUserHandle, Bot, Contact, FileHandle, FriendDetails, GroupDetails, GroupHandle,
+    MemberDetails, MemberHandle, MemberPermission, MessageHandle, OtherClientDetails, ReplyTarget,
+    StrangerDetails, UserHandle,
 };
 
 #[enum_dispatch]
@@ -20,7 +30,7 @@ trait AnyIncomingMessageNode {}
 #[allow(dead_code)]
 trait AnyOutgoingMessageNode {}
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct AtNode {
     #[serde(rename = "target")]
     pub target_id: i64,
@@ -42,7 +52,7 @@ pub fn at(target_id: i64) -> AtNode {
     AtNode { target_id }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct AtAllNode {}
 
 impl From<&AtAllNode> for AtAllNode {
@@ -55,7 +65,7 @@ pub fn at_all() -> AtAllNode {
     AtAllNode {}
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
 pub struct IncomingFaceNode {
     #[serde(rename = "faceId")]
     pub id: i32,
@@ -80,7 +90,7 @@ pub enum OutgoingFace<'a> {
     Name(Cow<'a, str>),
 }
 
-impl<'a> OutgoingFaceNode<'a> {
+impl OutgoingFaceNode<'_> {
     pub fn to_super_face(self, super_face: bool) -> Self {
         Self {
             face: self.face,
@@ -112,7 +122,7 @@ pub fn face_from_name<'a>(name: impl Into<Cow<'a, str>>) -> OutgoingFaceNode<'a>
     }
 }
 
-#[derive(Clone, Debug, IntoOwned, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, IntoOwned, Deserialize, Serialize)]
 pub struct PlainNode<'a> {
     pub text: Cow<'a, str>,
 }
@@ -137,6 +147,20 @@ pub struct IncomingImageNode {
     pub is_emoji: bool,
 }
 
+impl PartialEq for IncomingImageNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.image_id == other.image_id
+    }
+}
+
+impl Eq for IncomingImageNode {}
+
+impl Hash for IncomingImageNode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.image_id.hash(state);
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ImageType {
@@ -188,6 +212,20 @@ pub struct IncomingVoiceNode {
     pub length_secs: i64,
 }
 
+impl PartialEq for IncomingVoiceNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.voice_id == other.voice_id
+    }
+}
+
+impl Eq for IncomingVoiceNode {}
+
+impl Hash for IncomingVoiceNode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.voice_id.hash(state);
+    }
+}
+
 impl IncomingVoiceNode {
     pub fn length(&self) -> Duration {
         Duration::from_secs(self.length_secs as u64)
@@ -219,7 +257,7 @@ pub fn voice_from_base64<'a>(base64: impl Into<Cow<'a, str>>) -> OutgoingVoiceNo
     OutgoingVoiceNode::Base64(base64.into())
 }
 
-#[derive(Clone, Debug, IntoOwned, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, IntoOwned, Deserialize, Serialize)]
 pub struct XmlNode<'a> {
     #[serde(rename = "xml")]
     pub contents: Cow<'a, str>,
@@ -245,13 +283,19 @@ pub struct OutgoingJsonNode<'a> {
     pub contents: Cow<'a, str>,
 }
 
+impl OutgoingJsonNode<'_> {
+    pub fn parse(&self) -> serde_json::Result<Card> {
+        serde_json::from_str(&self.contents)
+    }
+}
+
 pub fn json<'a>(contents: impl Into<Cow<'a, str>>) -> OutgoingJsonNode<'a> {
     OutgoingJsonNode {
         contents: contents.into(),
     }
 }
 
-#[derive(Clone, Debug, IntoOwned, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, IntoOwned, Deserialize, Serialize)]
 pub struct AppNode<'a> {
     #[serde(rename = "content")]
     pub contents: Cow<'a, str>,
@@ -265,19 +309,74 @@ impl<'a> From<&'a AppNode<'a>> for AppNode<'a> {
     }
 }
 
+impl AppNode<'_> {
+    pub fn parse(&self) -> serde_json::Result<Card> {
+        serde_json::from_str(&self.contents)
+    }
+}
+
 pub fn app<'a>(contents: impl Into<Cow<'a, str>>) -> AppNode<'a> {
     AppNode {
         contents: contents.into(),
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// The common fields of a QQ JSON card, as carried by [`AppNode`] and [`OutgoingJsonNode`].
+///
+/// `meta` holds a single object keyed by `view`, e.g. `news` for shared links or `music` for
+/// shared songs, whose shape depends on the app; [`Card::title`] and [`Card::url`] look up the
+/// fields most cards use.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Card {
+    pub app: String,
+    pub view: String,
+    pub prompt: String,
+    pub meta: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Card {
+    /// The object in `meta` describing the shared content, preferring the one named by `view`.
+    pub fn details(&self) -> Option<&serde_json::Map<String, serde_json::Value>> {
+        self.meta
+            .get(&self.view)
+            .or_else(|| self.meta.values().next())?
+            .as_object()
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.details()?.get("title")?.as_str()
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        let details = self.details()?;
+        ["jumpUrl", "qqdocurl", "url"]
+            .into_iter()
+            .find_map(|key| details.get(key)?.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
 pub struct IncomingMarketFaceNode {
     pub id: i32,
     pub name: String,
 }
 
-#[derive(Clone, Debug, IntoOwned, Deserialize, Serialize)]
+impl IncomingMarketFaceNode {
+    /// The name without the surrounding brackets mirai adds, e.g. `骰子` for `[骰子]`.
+    pub fn display_name(&self) -> &str {
+        self.name
+            .strip_prefix('[')
+            .and_then(|name| name.strip_suffix(']'))
+            .unwrap_or(&self.name)
+    }
+}
+
+/// The legacy poke message, sent as part of a chain. This is unrelated to the nudge sent by e.g.
+/// [`FriendHandle::send_nudge`](crate::FriendHandle::send_nudge), which uses a separate endpoint
+/// and shows up as a tip rather than a message. mirai ignores unknown names, so prefer
+/// [`poke_by_name`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, IntoOwned, Deserialize, Serialize)]
 pub struct PokeNode<'a> {
     pub name: Cow<'a, str>,
 }
@@ -290,11 +389,78 @@ impl<'a> From<&'a PokeNode<'a>> for PokeNode<'a> {
     }
 }
 
+impl PokeNode<'_> {
+    pub fn kind(&self) -> Option<PokeKind> {
+        PokeKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == self.name)
+    }
+}
+
 pub fn poke<'a>(name: impl Into<Cow<'a, str>>) -> PokeNode<'a> {
     PokeNode { name: name.into() }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+/// Like [`poke`], but returns `None` for names mirai would ignore.
+pub fn poke_by_name(name: &str) -> Option<PokeNode<'static>> {
+    PokeKind::ALL
+        .into_iter()
+        .find(|kind| kind.name() == name)
+        .map(PokeNode::from)
+}
+
+impl From<PokeKind> for PokeNode<'static> {
+    fn from(value: PokeKind) -> Self {
+        poke(value.name())
+    }
+}
+
+/// The pokes mirai-api-http accepts, sent under the names returned by [`PokeKind::name`];
+/// [`PokeKind::display_name`] is what QQ shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PokeKind {
+    Poke,
+    ShowLove,
+    Like,
+    Heartbroken,
+    SixSixSix,
+    FangDaZhao,
+}
+
+impl PokeKind {
+    pub const ALL: [Self; 6] = [
+        Self::Poke,
+        Self::ShowLove,
+        Self::Like,
+        Self::Heartbroken,
+        Self::SixSixSix,
+        Self::FangDaZhao,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Poke => "Poke",
+            Self::ShowLove => "ShowLove",
+            Self::Like => "Like",
+            Self::Heartbroken => "Heartbroken",
+            Self::SixSixSix => "SixSixSix",
+            Self::FangDaZhao => "FangDaZhao",
+        }
+    }
+
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Self::Poke => "戳一戳",
+            Self::ShowLove => "比心",
+            Self::Like => "点赞",
+            Self::Heartbroken => "心碎",
+            Self::SixSixSix => "666",
+            Self::FangDaZhao => "放大招",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct DiceNode {
     pub value: i32,
 }
@@ -305,11 +471,34 @@ impl From<&DiceNode> for DiceNode {
     }
 }
 
+impl DiceNode {
+    /// The range of values mirai accepts and reports for a die.
+    pub const VALUES: RangeInclusive<i32> = 1..=6;
+
+    pub fn is_valid(&self) -> bool {
+        Self::VALUES.contains(&self.value)
+    }
+}
+
+/// A die showing `value`, which should be in [`DiceNode::VALUES`].
 pub fn dice(value: i32) -> DiceNode {
     DiceNode { value }
 }
 
-#[derive(Clone, Debug, IntoOwned, Deserialize, Serialize)]
+/// A die showing a uniformly random value from 1 to 6 drawn from `rng`. Pass a seeded RNG for
+/// reproducible rolls.
+#[cfg(feature = "rand")]
+pub fn dice_roll(rng: &mut (impl rand_core::RngCore + ?Sized)) -> DiceNode {
+    loop {
+        // Reject the top few values so that every face is equally likely.
+        let value = rng.next_u32();
+        if value < u32::MAX - u32::MAX % 6 {
+            return dice((value % 6) as i32 + 1);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, IntoOwned, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MusicShareNode<'a> {
     pub kind: Cow<'a, str>,
@@ -355,12 +544,86 @@ pub fn music_share<'a>(
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// A NetEase Cloud Music share of song `song_id`, with the urls mirai expects for the
+/// `NeteaseCloudMusic` kind filled in.
+pub fn music_share_netease<'a>(
+    title: impl Into<Cow<'a, str>>,
+    summary: impl Into<Cow<'a, str>>,
+    song_id: i64,
+    picture_url: impl Into<Cow<'a, str>>,
+) -> MusicShareNode<'a> {
+    let title = title.into();
+    music_share(
+        "NeteaseCloudMusic",
+        title.clone(),
+        summary,
+        format!("https://y.music.163.com/m/song?id={song_id}"),
+        picture_url,
+        format!("https://music.163.com/song/media/outer/url?id={song_id}.mp3"),
+        format!("[分享]{title}"),
+    )
+}
+
+/// A QQ Music share of the song with mid `song_mid`. QQ Music's audio urls are signed per
+/// request, so `music_url` has to be obtained from QQ Music and cannot be derived here.
+pub fn music_share_qq<'a>(
+    title: impl Into<Cow<'a, str>>,
+    summary: impl Into<Cow<'a, str>>,
+    song_mid: &str,
+    picture_url: impl Into<Cow<'a, str>>,
+    music_url: impl Into<Cow<'a, str>>,
+) -> MusicShareNode<'a> {
+    let title = title.into();
+    music_share(
+        "QQMusic",
+        title.clone(),
+        summary,
+        format!("https://i.y.qq.com/v8/playsong.html?songmid={song_mid}"),
+        picture_url,
+        music_url,
+        format!("[分享]{title}"),
+    )
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
 pub struct IncomingForwardNode {
     #[serde(rename = "nodeList")]
     pub messages: Vec<IncomingForwardedMessage>,
 }
 
+impl IncomingForwardNode {
+    /// Renders the forwarded messages as lines of `sender_name (time): text`, where `time` is
+    /// produced by `format_time` from the timestamp in seconds. Nested forwards follow the message
+    /// containing them, indented by two spaces per level.
+    pub fn transcript(&self, format_time: impl Fn(i32) -> String) -> String {
+        let mut transcript = String::new();
+        self.write_transcript(&mut transcript, 0, &format_time);
+        transcript
+    }
+
+    fn write_transcript(
+        &self,
+        transcript: &mut String,
+        depth: usize,
+        format_time: &dyn Fn(i32) -> String,
+    ) {
+        for message in &self.messages {
+            transcript.push_str(&"  ".repeat(depth));
+            transcript.push_str(&format!(
+                "{} ({}): {}\n",
+                message.sender_name,
+                format_time(message.time),
+                plain_text(&message.nodes),
+            ));
+            for node in &message.nodes {
+                if let IncomingMessageNode::Forward(node) = node {
+                    node.write_transcript(transcript, depth + 1, format_time);
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct IncomingForwardedMessage {
     pub sender_id: i64,
@@ -370,6 +633,27 @@ pub struct IncomingForwardedMessage {
     pub nodes: Vec<IncomingMessageNode>,
 }
 
+// the quote is left out, like in `IncomingMessageContents`
+impl PartialEq for IncomingForwardedMessage {
+    fn eq(&self, other: &Self) -> bool {
+        self.sender_id == other.sender_id
+            && self.sender_name == other.sender_name
+            && self.time == other.time
+            && self.nodes == other.nodes
+    }
+}
+
+impl Eq for IncomingForwardedMessage {}
+
+impl Hash for IncomingForwardedMessage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sender_id.hash(state);
+        self.sender_name.hash(state);
+        self.time.hash(state);
+        self.nodes.hash(state);
+    }
+}
+
 impl IncomingForwardedMessage {
     pub fn sender(&self) -> UserHandle {
         Bot.get_user(self.sender_id)
@@ -445,7 +729,7 @@ impl From<MessageHandle> for OutgoingForwardedMessage<'_> {
     }
 }
 
-impl<'a> Serialize for OutgoingForwardedMessage<'a> {
+impl Serialize for OutgoingForwardedMessage<'_> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         #[derive(Debug, Serialize)]
         struct Id {
@@ -504,6 +788,43 @@ pub struct ForwardDisplay<'a> {
 }
 
 impl<'a> ForwardDisplay<'a> {
+    /// How a forward of `messages` titled `title` looks when forwarded from a QQ client: a
+    /// `[聊天记录]` brief, up to four `sender: text` preview lines taken from the custom messages,
+    /// and a `查看N条转发消息` summary.
+    pub fn news(title: impl Into<Cow<'a, str>>, messages: &[OutgoingForwardedMessage<'_>]) -> Self {
+        const PREVIEW_LINES: usize = 4;
+        let preview = messages
+            .iter()
+            .filter_map(|message| match message {
+                OutgoingForwardedMessage::Custom(message) => Some(message),
+                OutgoingForwardedMessage::Ref(_) => None,
+            })
+            .take(PREVIEW_LINES)
+            .map(|message| {
+                let text = message
+                    .nodes
+                    .iter()
+                    .map(|node| match node {
+                        OutgoingMessageNode::Plain(node) => &node.text,
+                        OutgoingMessageNode::Face(_) => "[表情]",
+                        OutgoingMessageNode::Image(_) => "[图片]",
+                        OutgoingMessageNode::Voice(_) => "[语音]",
+                        OutgoingMessageNode::Forward(_) => "[聊天记录]",
+                        _ => "",
+                    })
+                    .collect::<String>();
+                Cow::Owned(format!("{}: {}", message.sender_name, text))
+            })
+            .collect();
+        Self {
+            brief: Some(Cow::Borrowed("[聊天记录]")),
+            preview: Some(preview),
+            source: None,
+            summary: Some(Cow::Owned(format!("查看{}条转发消息", messages.len()))),
+            title: Some(title.into()),
+        }
+    }
+
     pub fn into_owned(self) -> ForwardDisplay<'static> {
         ForwardDisplay {
             brief: self.brief.map(|val| val.into_owned().into()),
@@ -561,7 +882,7 @@ fn deserialize_file_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Str
     Ok(id)
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
 pub struct IncomingFileNode {
     #[serde(deserialize_with = "deserialize_file_id")]
     pub id: String,
@@ -595,6 +916,59 @@ pub struct IncomingShortVideoNode {
     pub md5: String,
 }
 
+impl PartialEq for IncomingShortVideoNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.video_id == other.video_id
+    }
+}
+
+impl Eq for IncomingShortVideoNode {}
+
+impl Hash for IncomingShortVideoNode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.video_id.hash(state);
+    }
+}
+
+impl IncomingShortVideoNode {
+    /// Downloads the video into memory. `fetch` opens its url as a stream, e.g. with the adapter's
+    /// own download helper, which is abandoned once it grows past `max_bytes`.
+    pub async fn download<'a, E, Fut, St>(
+        &'a self,
+        max_bytes: usize,
+        fetch: impl FnOnce(&'a str) -> Fut,
+    ) -> Result<Bytes, DownloadError<E>>
+    where
+        Fut: Future<Output = Result<St, E>>,
+        St: Stream<Item = Result<Bytes, E>>,
+    {
+        let url = self.url.as_deref().ok_or(DownloadError::MissingUrl)?;
+        if self.size > max_bytes as i64 {
+            return Err(DownloadError::TooLarge(max_bytes));
+        }
+        let mut chunks = pin!(fetch(url).await.map_err(DownloadError::Fetch)?);
+        let mut body = Vec::new();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.map_err(DownloadError::Fetch)?;
+            if body.len() + chunk.len() > max_bytes {
+                return Err(DownloadError::TooLarge(max_bytes));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body.into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Error)]
+pub enum DownloadError<E> {
+    #[error("no download url available")]
+    MissingUrl,
+    #[error("download exceeds {0} bytes")]
+    TooLarge(usize),
+    #[error("failed to download: {0}")]
+    Fetch(E),
+}
+
 #[derive(Clone, Debug, IntoOwned, Serialize)]
 pub struct OutgoingMiraiCodeNode<'a> {
     pub code: Cow<'a, str>,
@@ -604,8 +978,11 @@ pub fn mirai_code<'a>(code: impl Into<Cow<'a, str>>) -> OutgoingMiraiCodeNode<'a
     OutgoingMiraiCodeNode { code: code.into() }
 }
 
-#[derive(Clone, Debug)]
+/// Nodes compare by content, ignoring fields that vary between deliveries of the same content:
+/// images, voices and short videos compare by their id alone, not their download url.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, EnumDiscriminants)]
 #[enum_dispatch(AnyIncomingMessageNode)]
+#[strum_discriminants(name(IncomingMessageNodeKind), derive(Hash))]
 pub enum IncomingMessageNode {
     At(AtNode),
     AtAll(AtAllNode),
@@ -624,8 +1001,9 @@ pub enum IncomingMessageNode {
     ShortVideo(IncomingShortVideoNode),
 }
 
-#[derive(Clone, Debug, IntoOwned, Serialize)]
+#[derive(Clone, Debug, IntoOwned, Serialize, EnumDiscriminants)]
 #[enum_dispatch(AnyOutgoingMessageNode)]
+#[strum_discriminants(name(OutgoingMessageNodeKind), derive(Hash))]
 #[serde(tag = "type")]
 pub enum OutgoingMessageNode<'a> {
     At(AtNode),
@@ -678,6 +1056,16 @@ impl<'a> TryFrom<&'a IncomingMessageNode> for OutgoingMessageNode<'a> {
 #[error("cannot convert to outgoing message")]
 pub struct TryIntoOutgoingError;
 
+fn plain_text(nodes: &[IncomingMessageNode]) -> String {
+    nodes
+        .iter()
+        .filter_map(|node| match node {
+            IncomingMessageNode::Plain(node) => Some(node.text.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
 #[derive(Clone, Debug)]
 pub struct QuotedMessageContents {
     pub id: Option<i32>,
@@ -688,6 +1076,7 @@ pub struct QuotedMessageContents {
 pub trait AnyQuotedMessage {
     fn handle(&self) -> Option<MessageHandle>;
     fn contents(&self) -> &QuotedMessageContents;
+    fn sender_handle(&self) -> QuotedSender;
 
     fn id(&self) -> Option<i32> {
         self.contents().id
@@ -696,6 +1085,10 @@ pub trait AnyQuotedMessage {
     fn nodes(&self) -> &[IncomingMessageNode] {
         &self.contents().nodes
     }
+
+    fn text(&self) -> String {
+        plain_text(self.nodes())
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -717,16 +1110,23 @@ impl QuotedGroupMessage {
 
 impl AnyQuotedMessage for QuotedGroupMessage {
     fn handle(&self) -> Option<MessageHandle> {
-        Some(Bot.get_message(self.contents.id?, self.context_id))
+        Some(Bot.get_message_in(self.contents.id?, self.context()))
     }
 
     fn contents(&self) -> &QuotedMessageContents {
         &self.contents
     }
+
+    fn sender_handle(&self) -> QuotedSender {
+        QuotedSender::Member(self.sender())
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct QuotedUserMessage {
+    /// The other party of the chat the quoted message was sent in. Falls back to the sender when
+    /// the quote is not part of a friend or stranger message, e.g. in a forwarded message.
+    pub context_id: i64,
     pub receiver_id: i64,
     pub sender_id: i64,
     pub contents: QuotedMessageContents,
@@ -744,12 +1144,66 @@ impl QuotedUserMessage {
 
 impl AnyQuotedMessage for QuotedUserMessage {
     fn handle(&self) -> Option<MessageHandle> {
-        Some(Bot.get_message(self.contents.id?, self.sender_id))
+        Some(Bot.get_message(self.contents.id?, self.context_id))
     }
 
     fn contents(&self) -> &QuotedMessageContents {
         &self.contents
     }
+
+    fn sender_handle(&self) -> QuotedSender {
+        QuotedSender::User(self.sender())
+    }
+}
+
+/// A quote in a temp chat. mirai reports these like quotes between users, so they can only be
+/// told apart in a [`TempMessage`] or [`TempSyncMessage`], which know the group.
+#[derive(Clone, Debug)]
+pub struct QuotedTempMessage {
+    pub group_id: i64,
+    pub member_id: i64,
+    pub sender_id: i64,
+    pub contents: QuotedMessageContents,
+}
+
+impl QuotedTempMessage {
+    pub fn context(&self) -> MemberHandle {
+        Bot.get_group(self.group_id).get_member(self.member_id)
+    }
+
+    pub fn sender(&self) -> MemberHandle {
+        Bot.get_group(self.group_id).get_member(self.sender_id)
+    }
+}
+
+impl AnyQuotedMessage for QuotedTempMessage {
+    fn handle(&self) -> Option<MessageHandle> {
+        Some(Bot.get_message_in(self.contents.id?, self.context()))
+    }
+
+    fn contents(&self) -> &QuotedMessageContents {
+        &self.contents
+    }
+
+    fn sender_handle(&self) -> QuotedSender {
+        QuotedSender::Member(self.sender())
+    }
+}
+
+/// The sender of a quoted message, as a member when the quote was sent in a group or temp chat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QuotedSender {
+    User(UserHandle),
+    Member(MemberHandle),
+}
+
+impl AnyUserHandle for QuotedSender {
+    fn id(&self) -> i64 {
+        match self {
+            Self::User(user) => user.id(),
+            Self::Member(member) => member.id(),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -757,20 +1211,189 @@ impl AnyQuotedMessage for QuotedUserMessage {
 pub enum QuotedMessage {
     Group(QuotedGroupMessage),
     User(QuotedUserMessage),
+    Temp(QuotedTempMessage),
+}
+
+// The chat a message was received in, which the quote node alone does not reveal: mirai only
+// fills in its group id for group chats.
+enum QuoteContext {
+    User(i64),
+    Member { group_id: i64, member_id: i64 },
+}
+
+impl QuotedMessage {
+    fn in_context(self, context: QuoteContext) -> Self {
+        match (self, context) {
+            (Self::User(quote), QuoteContext::User(context_id)) => Self::User(QuotedUserMessage {
+                context_id,
+                ..quote
+            }),
+            (
+                Self::User(quote),
+                QuoteContext::Member {
+                    group_id,
+                    member_id,
+                },
+            ) => Self::Temp(QuotedTempMessage {
+                group_id,
+                member_id,
+                sender_id: quote.sender_id,
+                contents: quote.contents,
+            }),
+            (quote, _) => quote,
+        }
+    }
+}
+
+// Raw form of the message types whose quotes need the chat as context.
+#[derive(Debug, Deserialize)]
+struct RawChatMessage<C> {
+    #[serde(alias = "subject")]
+    sender: C,
+    #[serde(rename = "messageChain")]
+    contents: IncomingMessageContents,
+}
+
+impl<C> RawChatMessage<C> {
+    fn into_parts(self, context: QuoteContext) -> (C, IncomingMessageContents) {
+        let mut contents = self.contents;
+        contents.quote = contents.quote.map(|quote| quote.in_context(context));
+        (self.sender, contents)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct IncomingMessageContents {
+    /// Taken from the `Source` node. Some synced and relayed messages carry an id of zero, which
+    /// mirai cannot look up, so like the ids in quotes and recall events it becomes `None`. Such
+    /// messages can still be told apart by [`time_secs`](Self::time_secs), but cannot be recalled
+    /// or quoted.
     pub id: Option<i32>,
     pub time_secs: Option<i32>,
     pub quote: Option<QuotedMessage>,
     pub nodes: Vec<IncomingMessageNode>,
 }
 
+/// Contents compare by their nodes alone, so the same message delivered twice, or the same text
+/// sent as separate messages, compares equal regardless of id, time and quote.
+impl PartialEq for IncomingMessageContents {
+    fn eq(&self, other: &Self) -> bool {
+        self.nodes == other.nodes
+    }
+}
+
+impl Eq for IncomingMessageContents {}
+
+impl Hash for IncomingMessageContents {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.nodes.hash(state);
+    }
+}
+
 impl IncomingMessageContents {
     pub fn time(&self) -> Option<SystemTime> {
         SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(self.time_secs? as u64))
     }
+
+    pub fn to_outgoing_lossy(
+        &self,
+    ) -> (Vec<OutgoingMessageNode<'_>>, Vec<IncomingMessageNodeKind>) {
+        let mut nodes = Vec::new();
+        let mut skipped = Vec::new();
+        for node in &self.nodes {
+            match node.try_into() {
+                Ok(node) => nodes.push(node),
+                Err(TryIntoOutgoingError) => skipped.push(node.into()),
+            }
+        }
+        (nodes, skipped)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, kind: IncomingMessageNodeKind) -> bool {
+        self.nodes
+            .iter()
+            .any(|node| IncomingMessageNodeKind::from(node) == kind)
+    }
+
+    pub fn is_text_only(&self) -> bool {
+        self.nodes.iter().all(|node| {
+            matches!(
+                node,
+                IncomingMessageNode::At(_)
+                    | IncomingMessageNode::AtAll(_)
+                    | IncomingMessageNode::Face(_)
+                    | IncomingMessageNode::Plain(_)
+            )
+        })
+    }
+
+    pub fn has_media(&self) -> bool {
+        self.nodes.iter().any(|node| {
+            matches!(
+                node,
+                IncomingMessageNode::Image(_)
+                    | IncomingMessageNode::Voice(_)
+                    | IncomingMessageNode::MarketFace(_)
+                    | IncomingMessageNode::Forward(_)
+                    | IncomingMessageNode::File(_)
+                    | IncomingMessageNode::ShortVideo(_)
+            )
+        })
+    }
+
+    // The text node a command would be in: the first node after any leading mentions, such as
+    // the `@bot` clients insert when the user picks the bot from the mention list.
+    fn command_node(&self) -> Option<(usize, &str)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .find_map(|(index, node)| match node {
+                IncomingMessageNode::At(_) | IncomingMessageNode::AtAll(_) => None,
+                IncomingMessageNode::Plain(node) if node.text.trim().is_empty() => None,
+                IncomingMessageNode::Plain(node) => Some(Some((index, node.text.trim_start()))),
+                _ => Some(None),
+            })
+            .flatten()
+    }
+
+    /// Parses `<prefix><name> <args>...` from the first text node, skipping leading mentions.
+    /// Arguments are split on whitespace; nodes after the text node are not included and can be
+    /// accessed through [`IncomingMessageContents::nodes_after_command`].
+    pub fn command(&self, prefix: &str) -> Option<(String, Vec<String>)> {
+        let (_, text) = self.command_node()?;
+        let rest = text.strip_prefix(prefix)?;
+        if rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let mut words = rest.split_whitespace();
+        let name = words.next()?;
+        Some((name.to_owned(), words.map(str::to_owned).collect()))
+    }
+
+    /// The nodes following the text node [`IncomingMessageContents::command`] parses, e.g. an image
+    /// sent along with the command.
+    pub fn nodes_after_command(&self) -> &[IncomingMessageNode] {
+        match self.command_node() {
+            Some((index, _)) => &self.nodes[index + 1..],
+            None => &[],
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a IncomingMessageContents> for Vec<OutgoingMessageNode<'a>> {
+    type Error = TryIntoOutgoingError;
+
+    fn try_from(value: &'a IncomingMessageContents) -> Result<Self, Self::Error> {
+        value.nodes.iter().map(TryInto::try_into).collect()
+    }
 }
 
 #[derive(Clone, Copy, Debug, Serialize)]
@@ -786,12 +1409,155 @@ impl<'a> OutgoingMessageContents<'a> {
         Self { quote: None, nodes }
     }
 
+    /// Runtime counterpart of [`make_message!`](crate::make_message) for nodes built up dynamically, e.g. in a
+    /// `Vec`. The nodes must outlive the returned contents.
+    pub fn from_nodes(nodes: &'a [OutgoingMessageNode<'a>]) -> Self {
+        Self::new(nodes)
+    }
+
     pub fn quote(self, quote: Option<MessageHandle>) -> Self {
-        self.quote_id(quote.map(|message| message.id))
+        self.with_quote(quote.map(|message| message.id))
     }
 
+    /// Quotes a message resolved elsewhere, e.g. through [`MessageHandle::resolve`].
+    ///
+    /// mirai-api-http only accepts the id of the quoted message and looks it up in the chat the
+    /// new message is sent to, so the handle's context must be that chat.
+    pub fn quote_handle(self, quote: MessageHandle) -> Self {
+        self.quote(Some(quote))
+    }
+
+    /// Quotes a message by reference, e.g. one obtained through [`AnyMessage::message_ref`].
+    /// Only the id is sent, as with [`Self::quote_handle`].
+    pub fn quote_ref(self, quote: RefForwardedMessage) -> Self {
+        self.with_quote(Some(quote.id))
+    }
+
+    /// A bare id carries no context and only resolves if the quoted message belongs to the chat
+    /// the new message is sent to.
+    #[deprecated(note = "use `quote_handle` or `quote_ref`, which keep the context")]
     pub fn quote_id(self, quote: Option<i32>) -> Self {
-        Self { quote, ..self }
+        self.with_quote(quote)
+    }
+
+    // Id 0 is what mirai reports for a missing source, so it never refers to a message.
+    fn with_quote(self, quote: Option<i32>) -> Self {
+        Self {
+            quote: quote.filter(|&id| id != 0),
+            ..self
+        }
+    }
+
+    /// Checks the contents against constraints known to be enforced for the given kind of chat:
+    ///
+    /// - the message must not be empty;
+    /// - mentions ([`AtNode`] and [`AtAllNode`]) are only allowed in groups;
+    /// - voice, xml, json, app, poke, dice, music share and forward nodes must be the only node.
+    ///
+    /// Passing this check does not guarantee the server accepts the message.
+    pub fn validate(&self, kind: ChatKind) -> Result<(), ChainError> {
+        if self.nodes.is_empty() {
+            return Err(ChainError::Empty);
+        }
+        for node in self.nodes {
+            let node_kind = OutgoingMessageNodeKind::from(node);
+            match node_kind {
+                OutgoingMessageNodeKind::At | OutgoingMessageNodeKind::AtAll
+                    if kind != ChatKind::Group =>
+                {
+                    return Err(ChainError::MentionOutsideGroup(kind));
+                }
+                OutgoingMessageNodeKind::Voice
+                | OutgoingMessageNodeKind::Xml
+                | OutgoingMessageNodeKind::Json
+                | OutgoingMessageNodeKind::App
+                | OutgoingMessageNodeKind::Poke
+                | OutgoingMessageNodeKind::Dice
+                | OutgoingMessageNodeKind::MusicShare
+                | OutgoingMessageNodeKind::Forward
+                    if self.nodes.len() != 1 =>
+                {
+                    return Err(ChainError::NotAlone(node_kind));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn into_owned(self) -> OwnedOutgoingMessageContents {
+        OwnedOutgoingMessageContents {
+            quote: self.quote,
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| node.clone().into_owned())
+                .collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a [OutgoingMessageNode<'a>]> for OutgoingMessageContents<'a> {
+    fn from(nodes: &'a [OutgoingMessageNode<'a>]) -> Self {
+        Self::new(nodes)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChatKind {
+    Friend,
+    Group,
+    Temp,
+    OtherClient,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum ChainError {
+    #[error("message is empty")]
+    Empty,
+    #[error("mentions cannot be sent in {0:?} chats")]
+    MentionOutsideGroup(ChatKind),
+    #[error("{0:?} nodes must be sent alone")]
+    NotAlone(OutgoingMessageNodeKind),
+}
+
+/// Owned counterpart of [`OutgoingMessageContents`] that can be built in one task and sent from
+/// another.
+#[derive(Clone, Debug)]
+pub struct OwnedOutgoingMessageContents {
+    pub quote: Option<i32>,
+    pub nodes: Vec<OutgoingMessageNode<'static>>,
+}
+
+impl OwnedOutgoingMessageContents {
+    pub fn new(nodes: Vec<OutgoingMessageNode<'static>>) -> Self {
+        Self { quote: None, nodes }
+    }
+
+    pub fn quote(self, quote: Option<MessageHandle>) -> Self {
+        Self {
+            quote: quote.map(|message| message.id).filter(|&id| id != 0),
+            ..self
+        }
+    }
+
+    pub fn contents(&self) -> OutgoingMessageContents<'_> {
+        OutgoingMessageContents {
+            quote: self.quote,
+            nodes: &self.nodes,
+        }
+    }
+}
+
+impl From<Vec<OutgoingMessageNode<'static>>> for OwnedOutgoingMessageContents {
+    fn from(nodes: Vec<OutgoingMessageNode<'static>>) -> Self {
+        Self::new(nodes)
+    }
+}
+
+impl From<OutgoingMessageContents<'_>> for OwnedOutgoingMessageContents {
+    fn from(contents: OutgoingMessageContents<'_>) -> Self {
+        contents.into_owned()
     }
 }
 
@@ -804,8 +1570,64 @@ macro_rules! make_message {
   }};
 }
 
+#[macro_export]
+macro_rules! reply_message {
+  (to = $to:expr, quote = $quote:expr; $($x:expr),* $(,)?) => {{
+    $crate::message::OutgoingMessageContents::new(&[
+      $crate::__::Into::into($crate::message::at($crate::AnyUserHandle::id(&$to))),
+      $crate::__::Into::into(" "),
+      $($crate::__::Into::into($x),)*
+    ])
+    .quote($quote)
+  }};
+  (quote = $quote:expr; $($x:expr),* $(,)?) => {{
+    $crate::make_message![$($x),*].quote($quote)
+  }};
+}
+
+/// How JSON received from mirai-api-http is decoded by [`decode`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DecodeOptions {
+    /// Rejects message chains with more than one `Source` or `Quote` node. Otherwise the first of
+    /// each is kept and the rest are ignored.
+    pub strict: bool,
+}
+
+/// Decodes `value` with `options` applied to every message chain in it, however deeply nested.
+pub fn decode<T: DeserializeOwned>(
+    value: serde_json::Value,
+    options: DecodeOptions,
+) -> Result<T, serde_json::Error> {
+    if options.strict {
+        check_duplicate_nodes(&value)?;
+    }
+    T::deserialize(value)
+}
+
+// Message chains are the only arrays holding objects tagged as `Source` or `Quote`.
+fn check_duplicate_nodes(value: &serde_json::Value) -> Result<(), serde_json::Error> {
+    use serde::de::Error;
+
+    match value {
+        serde_json::Value::Array(items) => {
+            for kind in ["Source", "Quote"] {
+                let count = items
+                    .iter()
+                    .filter(|item| item.get("type").and_then(|kind| kind.as_str()) == Some(kind))
+                    .count();
+                if count > 1 {
+                    return Err(serde_json::Error::custom(format!("duplicate `{kind}`")));
+                }
+            }
+            items.iter().try_for_each(check_duplicate_nodes)
+        }
+        serde_json::Value::Object(fields) => fields.values().try_for_each(check_duplicate_nodes),
+        _ => Ok(()),
+    }
+}
+
 const _: () = {
-    use serde::de::{Error, SeqAccess, Visitor};
+    use serde::de::{SeqAccess, Visitor};
 
     struct IncomingMessageContentsVisitor;
 
@@ -862,8 +1684,9 @@ const _: () = {
             while let Some(node) = seq.next_element::<Impl>()? {
                 match node {
                     Impl::Source(node) => {
+                        // see `DecodeOptions::strict`
                         if time_secs.is_some() {
-                            return Err(A::Error::custom("duplicate `Source`"));
+                            continue;
                         }
                         id = (node.id != 0).then_some(node.id);
                         time_secs = Some(node.time);
@@ -878,7 +1701,7 @@ const _: () = {
                     Impl::App(node) => nodes.push(IncomingMessageNode::App(node)),
                     Impl::Quote(node) => {
                         if quote.is_some() {
-                            return Err(A::Error::custom("duplicate `Quote`"));
+                            continue;
                         }
                         let contents = QuotedMessageContents {
                             id: (node.id != 0).then_some(node.id),
@@ -886,6 +1709,7 @@ const _: () = {
                         };
                         quote = Some(if node.group_id == 0 {
                             QuotedMessage::User(QuotedUserMessage {
+                                context_id: node.sender_id,
                                 receiver_id: node.target_id,
                                 sender_id: node.sender_id,
                                 contents,
@@ -927,11 +1751,26 @@ const _: () = {
 pub trait AnyMessage {
     fn handle(&self) -> Option<MessageHandle>;
     fn contents(&self) -> &IncomingMessageContents;
+    fn reply_target(&self) -> Option<ReplyTarget>;
 
     fn id(&self) -> Option<i32> {
         self.contents().id
     }
 
+    /// A reference to this message for quoting or forwarding, available even when
+    /// [`AnyMessage::handle`] is `None`, e.g. for [`OtherClientMessage`]. Such references carry
+    /// only the source id, which mirai looks up in the chat the new message is sent to, so they
+    /// only resolve there. Messages without a source id cannot be referenced at all.
+    fn message_ref(&self) -> Option<RefForwardedMessage> {
+        match self.handle() {
+            Some(handle) => Some(handle.into()),
+            None => Some(RefForwardedMessage {
+                context: None,
+                id: self.id()?,
+            }),
+        }
+    }
+
     fn time(&self) -> Option<SystemTime> {
         self.contents().time()
     }
@@ -947,15 +1786,27 @@ pub trait AnyMessage {
     fn nodes(&self) -> &[IncomingMessageNode] {
         self.contents().nodes.as_ref()
     }
+
+    fn text(&self) -> String {
+        plain_text(self.nodes())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawChatMessage<FriendDetails>")]
 pub struct FriendMessage {
     pub sender: FriendDetails,
-    #[serde(rename = "messageChain")]
     pub contents: IncomingMessageContents,
 }
 
+impl From<RawChatMessage<FriendDetails>> for FriendMessage {
+    fn from(raw: RawChatMessage<FriendDetails>) -> Self {
+        let quote_context = QuoteContext::User(raw.sender.id());
+        let (sender, contents) = raw.into_parts(quote_context);
+        Self { sender, contents }
+    }
+}
+
 impl FriendMessage {
     pub fn context(&self) -> &FriendDetails {
         &self.sender
@@ -968,22 +1819,33 @@ impl FriendMessage {
 
 impl AnyMessage for FriendMessage {
     fn handle(&self) -> Option<MessageHandle> {
-        Some(Bot.get_message(self.contents.id?, self.sender.0.id))
+        Some(Bot.get_message_in(self.contents.id?, self.sender.handle()))
     }
 
     fn contents(&self) -> &IncomingMessageContents {
         &self.contents
     }
+
+    fn reply_target(&self) -> Option<ReplyTarget> {
+        Some(self.sender.handle().into())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawChatMessage<FriendDetails>")]
 pub struct FriendSyncMessage {
-    #[serde(rename = "subject")]
     pub context: FriendDetails,
-    #[serde(rename = "messageChain")]
     pub contents: IncomingMessageContents,
 }
 
+impl From<RawChatMessage<FriendDetails>> for FriendSyncMessage {
+    fn from(raw: RawChatMessage<FriendDetails>) -> Self {
+        let quote_context = QuoteContext::User(raw.sender.id());
+        let (context, contents) = raw.into_parts(quote_context);
+        Self { context, contents }
+    }
+}
+
 impl FriendSyncMessage {
     pub fn context(&self) -> &FriendDetails {
         &self.context
@@ -992,12 +1854,16 @@ impl FriendSyncMessage {
 
 impl AnyMessage for FriendSyncMessage {
     fn handle(&self) -> Option<MessageHandle> {
-        Some(Bot.get_message(self.contents.id?, self.context.0.id))
+        Some(Bot.get_message_in(self.contents.id?, self.context.handle()))
     }
 
     fn contents(&self) -> &IncomingMessageContents {
         &self.contents
     }
+
+    fn reply_target(&self) -> Option<ReplyTarget> {
+        Some(self.context.handle().into())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -1015,16 +1881,43 @@ impl GroupMessage {
     pub fn sender(&self) -> &MemberDetails {
         &self.sender
     }
+
+    /// The sender's handle if their permission is at least `required`, as of when the message
+    /// was sent.
+    pub fn require_permission(
+        &self,
+        required: MemberPermission,
+    ) -> Result<MemberHandle, PermissionDenied> {
+        if self.sender.permission >= required {
+            Ok(self.sender.handle())
+        } else {
+            Err(PermissionDenied {
+                required,
+                actual: self.sender.permission,
+            })
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+#[error("requires {required:?} permission, but the sender is {actual:?}")]
+pub struct PermissionDenied {
+    pub required: MemberPermission,
+    pub actual: MemberPermission,
 }
 
 impl AnyMessage for GroupMessage {
     fn handle(&self) -> Option<MessageHandle> {
-        Some(Bot.get_message(self.contents.id?, self.sender.group.id))
+        Some(Bot.get_message_in(self.contents.id?, self.sender.group.handle()))
     }
 
     fn contents(&self) -> &IncomingMessageContents {
         &self.contents
     }
+
+    fn reply_target(&self) -> Option<ReplyTarget> {
+        Some(self.sender.group.handle().into())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -1043,21 +1936,36 @@ impl GroupSyncMessage {
 
 impl AnyMessage for GroupSyncMessage {
     fn handle(&self) -> Option<MessageHandle> {
-        Some(Bot.get_message(self.contents.id?, self.context.id))
+        Some(Bot.get_message_in(self.contents.id?, self.context.handle()))
     }
 
     fn contents(&self) -> &IncomingMessageContents {
         &self.contents
     }
+
+    fn reply_target(&self) -> Option<ReplyTarget> {
+        Some(self.context.handle().into())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawChatMessage<MemberDetails>")]
 pub struct TempMessage {
     pub sender: MemberDetails,
-    #[serde(rename = "messageChain")]
     pub contents: IncomingMessageContents,
 }
 
+impl From<RawChatMessage<MemberDetails>> for TempMessage {
+    fn from(raw: RawChatMessage<MemberDetails>) -> Self {
+        let quote_context = QuoteContext::Member {
+            group_id: raw.sender.group.id,
+            member_id: raw.sender.id,
+        };
+        let (sender, contents) = raw.into_parts(quote_context);
+        Self { sender, contents }
+    }
+}
+
 impl TempMessage {
     pub fn context(&self) -> &MemberDetails {
         &self.sender
@@ -1066,49 +1974,94 @@ impl TempMessage {
     pub fn sender(&self) -> &MemberDetails {
         &self.sender
     }
+
+    /// Replies through the same temporary session, i.e. privately to the sender via the group the
+    /// message came from, never to the group itself.
+    pub async fn reply<'a, S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        message: &'a OutgoingMessageContents<'a>,
+    ) -> Result<MessageHandle, S::Error> {
+        self.sender.handle().send_message(session, message).await
+    }
 }
 
 impl AnyMessage for TempMessage {
     fn handle(&self) -> Option<MessageHandle> {
-        Some(Bot.get_message(self.contents.id?, self.sender.id))
+        Some(Bot.get_message_in(self.contents.id?, self.sender.handle()))
     }
 
     fn contents(&self) -> &IncomingMessageContents {
         &self.contents
     }
+
+    fn reply_target(&self) -> Option<ReplyTarget> {
+        Some(self.sender.handle().into())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawChatMessage<MemberDetails>")]
 pub struct TempSyncMessage {
-    #[serde(rename = "subject")]
     pub context: MemberDetails,
-    #[serde(rename = "messageChain")]
     pub contents: IncomingMessageContents,
 }
 
+impl From<RawChatMessage<MemberDetails>> for TempSyncMessage {
+    fn from(raw: RawChatMessage<MemberDetails>) -> Self {
+        let quote_context = QuoteContext::Member {
+            group_id: raw.sender.group.id,
+            member_id: raw.sender.id,
+        };
+        let (context, contents) = raw.into_parts(quote_context);
+        Self { context, contents }
+    }
+}
+
 impl TempSyncMessage {
     pub fn context(&self) -> &MemberDetails {
         &self.context
     }
+
+    /// Sends another message through the same temporary session.
+    pub async fn reply<'a, S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        message: &'a OutgoingMessageContents<'a>,
+    ) -> Result<MessageHandle, S::Error> {
+        self.context.handle().send_message(session, message).await
+    }
 }
 
 impl AnyMessage for TempSyncMessage {
     fn handle(&self) -> Option<MessageHandle> {
-        Some(Bot.get_message(self.contents.id?, self.context.id))
+        Some(Bot.get_message_in(self.contents.id?, self.context.handle()))
     }
 
     fn contents(&self) -> &IncomingMessageContents {
         &self.contents
     }
+
+    fn reply_target(&self) -> Option<ReplyTarget> {
+        Some(self.context.handle().into())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawChatMessage<StrangerDetails>")]
 pub struct StrangerMessage {
     pub sender: StrangerDetails,
-    #[serde(rename = "messageChain")]
     pub contents: IncomingMessageContents,
 }
 
+impl From<RawChatMessage<StrangerDetails>> for StrangerMessage {
+    fn from(raw: RawChatMessage<StrangerDetails>) -> Self {
+        let quote_context = QuoteContext::User(raw.sender.id());
+        let (sender, contents) = raw.into_parts(quote_context);
+        Self { sender, contents }
+    }
+}
+
 impl StrangerMessage {
     pub fn context(&self) -> &StrangerDetails {
         self.sender()
@@ -1127,16 +2080,27 @@ impl AnyMessage for StrangerMessage {
     fn contents(&self) -> &IncomingMessageContents {
         &self.contents
     }
+
+    fn reply_target(&self) -> Option<ReplyTarget> {
+        None
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawChatMessage<StrangerDetails>")]
 pub struct StrangerSyncMessage {
-    #[serde(rename = "subject")]
     pub context: StrangerDetails,
-    #[serde(rename = "messageChain")]
     pub contents: IncomingMessageContents,
 }
 
+impl From<RawChatMessage<StrangerDetails>> for StrangerSyncMessage {
+    fn from(raw: RawChatMessage<StrangerDetails>) -> Self {
+        let quote_context = QuoteContext::User(raw.sender.id());
+        let (context, contents) = raw.into_parts(quote_context);
+        Self { context, contents }
+    }
+}
+
 impl StrangerSyncMessage {
     pub fn context(&self) -> &StrangerDetails {
         &self.context
@@ -1151,6 +2115,10 @@ impl AnyMessage for StrangerSyncMessage {
     fn contents(&self) -> &IncomingMessageContents {
         &self.contents
     }
+
+    fn reply_target(&self) -> Option<ReplyTarget> {
+        None
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -1178,6 +2146,10 @@ impl AnyMessage for OtherClientMessage {
     fn contents(&self) -> &IncomingMessageContents {
         &self.contents
     }
+
+    fn reply_target(&self) -> Option<ReplyTarget> {
+        None
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -1203,3 +2175,318 @@ pub enum Message {
     #[serde(rename = "OtherClientMessage")]
     OtherClient(OtherClientMessage),
 }
+
+impl Message {
+    pub fn is_sync(&self) -> bool {
+        matches!(
+            self,
+            Self::FriendSync(_) | Self::GroupSync(_) | Self::TempSync(_) | Self::StrangerSync(_)
+        )
+    }
+
+    /// Returns whether this message was sent from the bot account, either as a sync message or
+    /// from another client logged in to it.
+    pub fn is_from_bot(&self, bot_id: i64) -> bool {
+        match self {
+            Self::Friend(message) => message.sender.id() == bot_id,
+            Self::Group(message) => message.sender.id == bot_id,
+            Self::Temp(message) => message.sender.id == bot_id,
+            Self::Stranger(message) => message.sender.id() == bot_id,
+            Self::FriendSync(_)
+            | Self::GroupSync(_)
+            | Self::TempSync(_)
+            | Self::StrangerSync(_)
+            | Self::OtherClient(_) => true,
+        }
+    }
+
+    /// Replies to this message with the same look as a QQ client: the message is quoted
+    /// through [`OutgoingMessageContents::quote`], and group messages additionally mention
+    /// the original sender. Returns `None` if this message cannot be replied to.
+    pub async fn reply_quoting<S: MahSession + ?Sized>(
+        &self,
+        session: &S,
+        nodes: &[OutgoingMessageNode<'_>],
+    ) -> Result<Option<MessageHandle>, S::Error> {
+        let Some(target) = self.reply_target() else {
+            return Ok(None);
+        };
+        let mut chain = Vec::with_capacity(nodes.len() + 2);
+        if let Self::Group(message) = self {
+            chain.push(at(message.sender.id).into());
+            chain.push(" ".into());
+        }
+        chain.extend_from_slice(nodes);
+        let message = OutgoingMessageContents::new(&chain).quote(self.handle());
+        target.send_message(session, &message).await.map(Some)
+    }
+}
+
+/// A node as far as [`SendTracker`] compares it: adjacent plain text is joined, and other nodes
+/// compare by kind, images and voices also by id if the sent node gave one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum EchoNode {
+    Text(String),
+    Media(IncomingMessageNodeKind, Option<String>),
+    Other(IncomingMessageNodeKind),
+}
+
+impl EchoNode {
+    fn matches(&self, echo: &Self) -> bool {
+        match (self, echo) {
+            (Self::Media(kind, None), Self::Media(echo_kind, _)) => kind == echo_kind,
+            _ => self == echo,
+        }
+    }
+}
+
+fn push_echo_node(fingerprint: &mut Vec<EchoNode>, node: EchoNode) {
+    match (fingerprint.last_mut(), node) {
+        (_, EchoNode::Text(text)) if text.is_empty() => {}
+        (Some(EchoNode::Text(last)), EchoNode::Text(text)) => last.push_str(&text),
+        (_, node) => fingerprint.push(node),
+    }
+}
+
+// Mirai code is only turned into nodes by the server, so messages containing it have no
+// fingerprint and are never matched.
+fn outgoing_fingerprint(nodes: &[OutgoingMessageNode<'_>]) -> Option<Vec<EchoNode>> {
+    let mut fingerprint = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let node = match node {
+            OutgoingMessageNode::Plain(node) => EchoNode::Text(node.text.to_string()),
+            OutgoingMessageNode::Image(node) => EchoNode::Media(
+                IncomingMessageNodeKind::Image,
+                match node {
+                    OutgoingImageNode::ImageId(id) => Some(id.to_string()),
+                    _ => None,
+                },
+            ),
+            OutgoingMessageNode::Voice(node) => EchoNode::Media(
+                IncomingMessageNodeKind::Voice,
+                match node {
+                    OutgoingVoiceNode::VoiceId(id) => Some(id.to_string()),
+                    _ => None,
+                },
+            ),
+            OutgoingMessageNode::At(_) => EchoNode::Other(IncomingMessageNodeKind::At),
+            OutgoingMessageNode::AtAll(_) => EchoNode::Other(IncomingMessageNodeKind::AtAll),
+            OutgoingMessageNode::Face(_) => EchoNode::Other(IncomingMessageNodeKind::Face),
+            OutgoingMessageNode::Xml(_) => EchoNode::Other(IncomingMessageNodeKind::Xml),
+            // JSON messages come back as app messages.
+            OutgoingMessageNode::Json(_) | OutgoingMessageNode::App(_) => {
+                EchoNode::Other(IncomingMessageNodeKind::App)
+            }
+            OutgoingMessageNode::Poke(_) => EchoNode::Other(IncomingMessageNodeKind::Poke),
+            OutgoingMessageNode::Dice(_) => EchoNode::Other(IncomingMessageNodeKind::Dice),
+            OutgoingMessageNode::MusicShare(_) => {
+                EchoNode::Other(IncomingMessageNodeKind::MusicShare)
+            }
+            OutgoingMessageNode::Forward(_) => EchoNode::Other(IncomingMessageNodeKind::Forward),
+            OutgoingMessageNode::MiraiCode(_) => return None,
+        };
+        push_echo_node(&mut fingerprint, node);
+    }
+    Some(fingerprint)
+}
+
+fn incoming_fingerprint(nodes: &[IncomingMessageNode]) -> Vec<EchoNode> {
+    let mut fingerprint = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let node = match node {
+            IncomingMessageNode::Plain(node) => EchoNode::Text(node.text.to_string()),
+            IncomingMessageNode::Image(node) => {
+                EchoNode::Media(IncomingMessageNodeKind::Image, Some(node.image_id.clone()))
+            }
+            IncomingMessageNode::Voice(node) => {
+                EchoNode::Media(IncomingMessageNodeKind::Voice, Some(node.voice_id.clone()))
+            }
+            node => EchoNode::Other(node.into()),
+        };
+        push_echo_node(&mut fingerprint, node);
+    }
+    fingerprint
+}
+
+/// Matches sync messages against messages recently sent by this client, telling apart echoes of
+/// our own sends from messages sent by other clients logged in to the bot account. Messages are
+/// compared by their plain text, the kinds of their other nodes and the ids of their images and
+/// voices.
+#[derive(Clone, Debug)]
+pub struct SendTracker {
+    window: Duration,
+    pending: VecDeque<(MessageHandle, Vec<EchoNode>, Instant)>,
+}
+
+impl SendTracker {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Records a sent message. Messages containing mirai code are never matched.
+    pub fn record(&mut self, handle: MessageHandle, message: &OutgoingMessageContents<'_>) {
+        if let Some(fingerprint) = outgoing_fingerprint(message.nodes) {
+            self.pending
+                .push_back((handle, fingerprint, Instant::now()));
+        }
+    }
+
+    pub fn match_echo(&mut self, message: &Message) -> Option<MessageHandle> {
+        let (context, contents) = match message {
+            Message::FriendSync(message) => (message.context.0.id, &message.contents),
+            Message::GroupSync(message) => (message.context.id, &message.contents),
+            Message::TempSync(message) => (message.context.id, &message.contents),
+            Message::StrangerSync(message) => (message.context.0.id, &message.contents),
+            _ => return None,
+        };
+        let now = Instant::now();
+        while self
+            .pending
+            .front()
+            .is_some_and(|&(_, _, time)| now.duration_since(time) > self.window)
+        {
+            self.pending.pop_front();
+        }
+        let echo = incoming_fingerprint(&contents.nodes);
+        let index = self.pending.iter().position(|(handle, sent, _)| {
+            handle.context == context
+                && sent.len() == echo.len()
+                && sent
+                    .iter()
+                    .zip(&echo)
+                    .all(|(sent, echo)| sent.matches(echo))
+        })?;
+        self.pending.remove(index).map(|(handle, _, _)| handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::{stream, StreamExt as _};
+
+    use super::{
+        at, image_from_id, image_from_url, DownloadError, IncomingShortVideoNode, Message,
+        OutgoingMessageContents, OutgoingMessageNode, SendTracker,
+    };
+    use crate::adapter::Bytes;
+    use crate::testing::try_parse_event;
+    use crate::{event::MessageOrEvent, Bot};
+
+    const GROUP: i64 = 987654321;
+
+    fn group_sync(chain: &str) -> Message {
+        let json = format!(
+            r#"{{
+                "type": "GroupSyncMessage",
+                "subject": {{ "id": {GROUP}, "name": "Group", "permission": "ADMINISTRATOR" }},
+                "messageChain": [{{ "type": "Source", "id": 4321, "time": 1700000000 }}, {chain}]
+            }}"#
+        );
+        let MessageOrEvent::Message(message) = try_parse_event(&json).unwrap() else {
+            panic!("expected a message");
+        };
+        message
+    }
+
+    fn image(id: &str) -> String {
+        format!(
+            r#"{{ "type": "Image", "imageId": "{id}", "url": "https://example.com/{id}",
+                "width": 1, "height": 1, "size": 1, "imageType": "PNG", "isEmoji": false }}"#
+        )
+    }
+
+    fn record(tracker: &mut SendTracker, id: i32, nodes: &[OutgoingMessageNode<'_>]) {
+        let handle = Bot.get_message(id, GROUP);
+        tracker.record(handle, &OutgoingMessageContents::new(nodes));
+    }
+
+    #[test]
+    fn matches_echo_by_node_kinds() {
+        let mut tracker = SendTracker::new(Duration::from_secs(60));
+        record(&mut tracker, 1, &["hi".into()]);
+        record(&mut tracker, 2, &[at(10000).into(), "hi".into()]);
+        let echo = group_sync(
+            r#"{ "type": "At", "target": 10000, "display": "@Alice" }, { "type": "Plain", "text": "hi" }"#,
+        );
+        // same plain text as the first message, but only the second has a mention
+        assert_eq!(tracker.match_echo(&echo), Some(Bot.get_message(2, GROUP)));
+        assert_eq!(
+            tracker.match_echo(&group_sync(r#"{ "type": "Plain", "text": "hi" }"#)),
+            Some(Bot.get_message(1, GROUP)),
+        );
+        assert_eq!(tracker.match_echo(&echo), None);
+    }
+
+    #[test]
+    fn matches_echo_by_image_id() {
+        let mut tracker = SendTracker::new(Duration::from_secs(60));
+        record(&mut tracker, 1, &[image_from_id("{A}.png").into()]);
+        record(&mut tracker, 2, &[image_from_id("{B}.png").into()]);
+        assert_eq!(
+            tracker.match_echo(&group_sync(&image("{B}.png"))),
+            Some(Bot.get_message(2, GROUP)),
+        );
+        assert_eq!(tracker.match_echo(&group_sync(&image("{C}.png"))), None);
+        // the id of an image sent by url is only known from the echo
+        record(
+            &mut tracker,
+            3,
+            &[image_from_url("https://example.com").into()],
+        );
+        assert_eq!(
+            tracker.match_echo(&group_sync(&image("{C}.png"))),
+            Some(Bot.get_message(3, GROUP)),
+        );
+    }
+
+    #[test]
+    fn joins_adjacent_plain_text() {
+        let mut tracker = SendTracker::new(Duration::from_secs(60));
+        record(&mut tracker, 1, &["hello, ".into(), "world".into()]);
+        assert_eq!(
+            tracker.match_echo(&group_sync(
+                r#"{ "type": "Plain", "text": "hello, world" }"#
+            )),
+            Some(Bot.get_message(1, GROUP)),
+        );
+    }
+
+    fn short_video(size: i64) -> IncomingShortVideoNode {
+        IncomingShortVideoNode {
+            video_id: "video".into(),
+            name: "video.mp4".into(),
+            size,
+            video_type: "mp4".into(),
+            url: Some("https://example.com/video.mp4".into()),
+            md5: String::new(),
+        }
+    }
+
+    async fn download(
+        node: &IncomingShortVideoNode,
+        max_bytes: usize,
+        chunks: &'static [&'static str],
+    ) -> Result<Bytes, DownloadError<()>> {
+        node.download(max_bytes, |_| async {
+            Ok(stream::iter(chunks).map(|chunk| Ok(Bytes::from_static(chunk.as_bytes()))))
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn download_stays_within_max_bytes() {
+        let body = download(&short_video(8), 8, &["abcd", "efgh"]).await;
+        assert_eq!(body.unwrap(), "abcdefgh");
+        // reported too large, so not fetched at all
+        let err = download(&short_video(9), 8, &[]).await.unwrap_err();
+        assert!(matches!(err, DownloadError::TooLarge(8)), "{err:?}");
+        // larger than reported
+        let err = download(&short_video(8), 8, &["abcd", "efgh", "i"]).await;
+        assert!(matches!(err, Err(DownloadError::TooLarge(8))), "{err:?}");
+    }
+}
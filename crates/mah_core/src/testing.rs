@@ -0,0 +1,177 @@
+use crate::event::MessageOrEvent;
+use crate::message::{decode, DecodeOptions, IncomingMessageContents};
+
+pub fn try_parse_event(json: &str) -> Result<MessageOrEvent, serde_json::Error> {
+    try_parse_event_with(json, DecodeOptions::default())
+}
+
+pub fn try_parse_event_with(
+    json: &str,
+    options: DecodeOptions,
+) -> Result<MessageOrEvent, serde_json::Error> {
+    decode(serde_json::from_str(json)?, options)
+}
+
+pub fn try_parse_message_chain(json: &str) -> Result<IncomingMessageContents, serde_json::Error> {
+    try_parse_message_chain_with(json, DecodeOptions::default())
+}
+
+pub fn try_parse_message_chain_with(
+    json: &str,
+    options: DecodeOptions,
+) -> Result<IncomingMessageContents, serde_json::Error> {
+    decode(serde_json::from_str(json)?, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        try_parse_event, try_parse_event_with, try_parse_message_chain,
+        try_parse_message_chain_with,
+    };
+    use crate::event::{Event, MessageOrEvent};
+    use crate::message::{
+        AnyQuotedMessage as _, DecodeOptions, IncomingMessageNode, Message, QuotedMessage,
+    };
+
+    const GROUP_MESSAGE: &str = r#"{
+        "type": "GroupMessage",
+        "sender": {
+            "id": 123456789,
+            "memberName": "Alice",
+            "specialTitle": "",
+            "permission": "MEMBER",
+            "joinTimestamp": 1600000000,
+            "lastSpeakTimestamp": 1700000000,
+            "muteTimeRemaining": 0,
+            "group": { "id": 987654321, "name": "Group", "permission": "ADMINISTRATOR" }
+        },
+        "messageChain": [
+            { "type": "Source", "id": 4321, "time": 1700000000 },
+            {
+                "type": "Quote",
+                "id": 4320,
+                "groupId": 987654321,
+                "senderId": 10000,
+                "targetId": 987654321,
+                "origin": [{ "type": "Plain", "text": "ping" }]
+            },
+            { "type": "At", "target": 10000, "display": "@Bot" },
+            { "type": "Plain", "text": " pong" }
+        ]
+    }"#;
+
+    const GROUP_RECALL_EVENT: &str = r#"{
+        "type": "GroupRecallEvent",
+        "authorId": 123456789,
+        "messageId": 4321,
+        "time": 1700000060,
+        "group": { "id": 987654321, "name": "Group", "permission": "ADMINISTRATOR" },
+        "operator": null
+    }"#;
+
+    #[test]
+    fn parses_group_message() {
+        let MessageOrEvent::Message(Message::Group(message)) =
+            try_parse_event(GROUP_MESSAGE).unwrap()
+        else {
+            panic!("not a group message");
+        };
+        assert_eq!(message.sender.id, 123456789);
+        assert_eq!(message.sender.group.id, 987654321);
+        assert_eq!(message.contents.id, Some(4321));
+        assert_eq!(message.contents.time_secs, Some(1700000000));
+        let Some(QuotedMessage::Group(quote)) = &message.contents.quote else {
+            panic!("missing group quote");
+        };
+        assert_eq!(quote.id(), Some(4320));
+        assert_eq!(quote.sender_id, 10000);
+        assert_eq!(quote.text(), "ping");
+        assert!(matches!(
+            message.contents.nodes[..],
+            [IncomingMessageNode::At(_), IncomingMessageNode::Plain(_)],
+        ));
+    }
+
+    #[test]
+    fn parses_recall_event() {
+        let MessageOrEvent::Event(Event::GroupMessageRecall(event)) =
+            try_parse_event(GROUP_RECALL_EVENT).unwrap()
+        else {
+            panic!("not a group recall event");
+        };
+        assert_eq!(event.message_id, 4321);
+        assert_eq!(event.sender_id, 123456789);
+        assert!(event.operator.is_none());
+    }
+
+    #[test]
+    fn parses_message_chain() {
+        let contents = try_parse_message_chain(
+            r#"[
+                { "type": "Source", "id": 4321, "time": 1700000000 },
+                { "type": "Face", "faceId": 178, "name": "斜眼笑", "isSuperFace": false },
+                { "type": "Plain", "text": "hi" }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(contents.id, Some(4321));
+        assert_eq!(contents.nodes.len(), 2);
+    }
+
+    const DUPLICATE_NODES: &str = r#"[
+        { "type": "Source", "id": 4321, "time": 1700000000 },
+        { "type": "Quote", "id": 1, "groupId": 0, "senderId": 1, "targetId": 2, "origin": [] },
+        { "type": "Plain", "text": "hi" },
+        { "type": "Source", "id": 4322, "time": 1700000001 },
+        { "type": "Quote", "id": 2, "groupId": 0, "senderId": 1, "targetId": 2, "origin": [] }
+    ]"#;
+
+    #[test]
+    fn keeps_first_duplicate_by_default() {
+        let contents = try_parse_message_chain(DUPLICATE_NODES).unwrap();
+        assert_eq!(contents.id, Some(4321));
+        assert_eq!(contents.time_secs, Some(1700000000));
+        assert_eq!(contents.quote.unwrap().id(), Some(1));
+        assert_eq!(contents.nodes.len(), 1);
+    }
+
+    #[test]
+    fn rejects_duplicates_when_strict() {
+        let strict = DecodeOptions { strict: true };
+        let err = try_parse_message_chain_with(DUPLICATE_NODES, strict).unwrap_err();
+        assert!(err.to_string().contains("duplicate `Source`"));
+        let quotes = r#"[
+            { "type": "Quote", "id": 1, "groupId": 0, "senderId": 1, "targetId": 2, "origin": [] },
+            { "type": "Quote", "id": 2, "groupId": 0, "senderId": 1, "targetId": 2, "origin": [] }
+        ]"#;
+        let err = try_parse_message_chain_with(quotes, strict).unwrap_err();
+        assert!(err.to_string().contains("duplicate `Quote`"));
+    }
+
+    #[test]
+    fn rejects_nested_duplicates_when_strict() {
+        let event = GROUP_MESSAGE.replace(
+            r#"{ "type": "Plain", "text": " pong" }"#,
+            r#"{ "type": "Source", "id": 4322, "time": 1700000000 }"#,
+        );
+        assert!(try_parse_event(&event).is_ok());
+        let err = try_parse_event_with(&event, DecodeOptions { strict: true }).unwrap_err();
+        assert!(err.to_string().contains("duplicate `Source`"));
+    }
+
+    #[test]
+    fn zero_source_id_is_none() {
+        let contents = try_parse_message_chain(
+            r#"[{ "type": "Source", "id": 0, "time": 1700000000 }, { "type": "Plain", "text": "hi" }]"#,
+        )
+        .unwrap();
+        assert_eq!(contents.id, None);
+        assert_eq!(contents.time_secs, Some(1700000000));
+    }
+
+    #[test]
+    fn rejects_unknown_event() {
+        assert!(try_parse_event(r#"{ "type": "NoSuchEvent" }"#).is_err());
+    }
+}
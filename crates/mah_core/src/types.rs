@@ -53,6 +53,9 @@ pub enum SubjectKind {
     Stranger,
 }
 
+/// Nothing ties `target` to the kind of chat the args are sent to; prefer the `send_message` methods
+/// on the typed handles, such as [`FriendHandle`](crate::FriendHandle) and
+/// [`GroupHandle`](crate::GroupHandle), which always pick the matching endpoint.
 #[derive(Clone, Copy, Debug, Serialize)]
 pub struct SendMessageArgs<'a> {
     pub target: i64,
@@ -68,10 +71,27 @@ pub struct SendTempMessageArgs<'a> {
     pub contents: &'a OutgoingMessageContents<'a>,
 }
 
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendRawMessageArgs<'a> {
+    pub target: i64,
+    pub message_chain: &'a serde_json::Value,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendRawTempMessageArgs<'a> {
+    pub qq: i64,
+    pub group: i64,
+    pub message_chain: &'a serde_json::Value,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SendMessageResult {
     pub message_id: i32,
+    #[serde(default)]
+    pub time: Option<i64>,
 }
 
 impl<E> From<SendMessageResult> for Result<i32, E>
@@ -91,6 +111,25 @@ where
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct SendMessageDetails {
+    pub message_id: i32,
+    pub time_secs: Option<i64>,
+}
+
+impl<E> From<SendMessageResult> for Result<SendMessageDetails, E>
+where
+    adapter::Error: Into<E>,
+{
+    fn from(value: SendMessageResult) -> Self {
+        let time_secs = value.time;
+        Result::<i32, E>::from(value).map(|message_id| SendMessageDetails {
+            message_id,
+            time_secs,
+        })
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, IntoStaticStr)]
 pub enum MediaType {
     Friend,
@@ -105,6 +144,14 @@ pub struct MessageIdArgs {
     pub message_id: i32,
 }
 
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionArgs {
+    pub target: i64,
+    pub message_id: i32,
+    pub face_id: i32,
+}
+
 #[derive(Clone, Copy, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RoamingMessagesArgs {
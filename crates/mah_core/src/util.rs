@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let units = [
+        (secs / 86400, "d"),
+        (secs / 3600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+        (secs % 60, "s"),
+    ];
+    let parts = units
+        .iter()
+        .skip_while(|&&(value, _)| value == 0)
+        .take(2)
+        .filter(|&&(value, _)| value != 0)
+        .map(|(value, unit)| format!("{value}{unit}"))
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        "0s".into()
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::format_duration;
+
+    #[test]
+    fn keeps_two_largest_units() {
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(3600 + 5)), "1h");
+        assert_eq!(
+            format_duration(Duration::from_secs(86400 * 2 + 3600 * 3 + 7)),
+            "2d 3h"
+        );
+    }
+}
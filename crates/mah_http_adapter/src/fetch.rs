@@ -1,7 +1,15 @@
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fs, io};
 
 use async_trait::async_trait;
-use reqwest::{Request, Response};
+use mah_core::adapter::{error_codes, Bytes};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::{Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
 
 #[async_trait]
 pub trait Fetch: Clone + Debug + Send + Sync {
@@ -35,3 +43,341 @@ impl Fetch for DefaultFetch {
         self.client.execute(request).await
     }
 }
+
+const LATENCY_SAMPLES: usize = 1024;
+
+#[derive(Debug, Default)]
+struct EndpointStats {
+    requests: u64,
+    errors: u64,
+    latencies: VecDeque<Duration>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EndpointMetrics {
+    pub requests: u64,
+    pub errors: u64,
+    pub p50: Option<Duration>,
+    pub p99: Option<Duration>,
+}
+
+/// Request statistics collected by [`MetricsFetch`], keyed by endpoint. Latency percentiles are
+/// computed over the most recent 1024 requests to each endpoint.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    endpoints: Mutex<HashMap<String, EndpointStats>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, EndpointMetrics> {
+        let endpoints = self.endpoints.lock().unwrap();
+        endpoints
+            .iter()
+            .map(|(endpoint, stats)| {
+                let mut latencies = Vec::from_iter(stats.latencies.iter().copied());
+                latencies.sort_unstable();
+                let percentile = |p: usize| {
+                    let last = latencies.len().checked_sub(1)?;
+                    latencies.get(last * p / 100).copied()
+                };
+                let metrics = EndpointMetrics {
+                    requests: stats.requests,
+                    errors: stats.errors,
+                    p50: percentile(50),
+                    p99: percentile(99),
+                };
+                (endpoint.clone(), metrics)
+            })
+            .collect()
+    }
+
+    fn record(&self, endpoint: &str, latency: Duration, success: bool) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = match endpoints.get_mut(endpoint) {
+            Some(stats) => stats,
+            None => endpoints.entry(endpoint.to_owned()).or_default(),
+        };
+        stats.requests += 1;
+        if !success {
+            stats.errors += 1;
+        }
+        if stats.latencies.len() == LATENCY_SAMPLES {
+            stats.latencies.pop_front();
+        }
+        stats.latencies.push_back(latency);
+    }
+}
+
+/// Records every request passing through `F` into a shared [`Metrics`]. Requests are labelled by
+/// their url path without the leading slash, e.g. `sendGroupMessage` when the adapter's endpoint
+/// is at the root of its host. Failed requests and non-success statuses count as errors.
+#[derive(Clone, Debug)]
+pub struct MetricsFetch<F = DefaultFetch> {
+    inner: F,
+    metrics: Arc<Metrics>,
+}
+
+impl<F: Fetch> MetricsFetch<F> {
+    pub fn new(inner: F, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+}
+
+#[async_trait]
+impl<F: Fetch> Fetch for MetricsFetch<F> {
+    async fn fetch(&self, request: Request) -> Result<Response, reqwest::Error> {
+        let endpoint = request.url().path().trim_start_matches('/').to_owned();
+        let start = Instant::now();
+        let result = self.inner.fetch(request).await;
+        let success = result
+            .as_ref()
+            .is_ok_and(|response| response.status().is_success());
+        self.metrics.record(&endpoint, start.elapsed(), success);
+        result
+    }
+}
+
+// FNV-1a, whose output unlike `DefaultHasher`'s is guaranteed not to change between releases, so
+// recordings keep their names.
+fn stable_hash(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Requests are identified by method, path, query and body. The host is left out so recordings can
+// be replayed against any endpoint, and the session key needs no special treatment because replay
+// hands out the recorded one.
+fn exchange_key(request: &Request) -> String {
+    let url = request.url();
+    let body = request.body().and_then(|body| body.as_bytes());
+    let hash = stable_hash(
+        [request.method().as_str(), " ", url.path(), "?"]
+            .into_iter()
+            .chain(url.query())
+            .chain(["\n"])
+            .flat_map(str::bytes)
+            .chain(body.unwrap_or_default().iter().copied()),
+    );
+    format!("{hash:016x}")
+}
+
+// Identical requests usually get different responses over time (e.g. `fetchMessage`), so each
+// occurrence of a key is numbered.
+#[derive(Debug, Default)]
+struct Sequence {
+    counts: Mutex<HashMap<String, usize>>,
+}
+
+impl Sequence {
+    fn name(&self, request: &Request) -> String {
+        let key = exchange_key(request);
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(key.clone()).or_default();
+        let name = format!("{key}-{count}");
+        *count += 1;
+        name
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct RecordedExchange {
+    method: String,
+    url: String,
+    request: Option<String>,
+    status: u16,
+    content_type: Option<String>,
+}
+
+fn build_response(status: StatusCode, headers: HeaderMap, body: Bytes) -> Response {
+    let mut response = http::Response::new(body);
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    response.into()
+}
+
+/// Records every exchange passing through `F` into a directory for [`ReplayFetch`]; meant for tests.
+///
+/// # Panics
+///
+/// [`Fetch::fetch`] panics if a recording cannot be written, since a [`reqwest::Error`] cannot
+/// carry an I/O error.
+#[derive(Clone, Debug)]
+pub struct RecordingFetch<F = DefaultFetch> {
+    inner: F,
+    dir: Arc<PathBuf>,
+    sequence: Arc<Sequence>,
+}
+
+impl<F: Fetch> RecordingFetch<F> {
+    pub fn new(inner: F, dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            inner,
+            dir: Arc::new(dir),
+            sequence: Default::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl<F: Fetch> Fetch for RecordingFetch<F> {
+    async fn fetch(&self, request: Request) -> Result<Response, reqwest::Error> {
+        let name = self.sequence.name(&request);
+        let method = request.method().to_string();
+        let url = request.url().to_string();
+        let body = request.body().and_then(|body| body.as_bytes());
+        let body = body.map(|body| String::from_utf8_lossy(body).into_owned());
+        let response = self.inner.fetch(request).await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let content_type = headers.get(CONTENT_TYPE);
+        let exchange = RecordedExchange {
+            method,
+            url,
+            request: body,
+            status: status.as_u16(),
+            content_type: content_type.and_then(|value| Some(value.to_str().ok()?.to_owned())),
+        };
+        let body = response.bytes().await?;
+        let exchange = serde_json::to_vec_pretty(&exchange).unwrap();
+        fs::write(self.dir.join(format!("{name}.json")), exchange)
+            .and_then(|()| fs::write(self.dir.join(format!("{name}.body")), &body))
+            .unwrap_or_else(|err| panic!("failed to write recording {name}: {err}"));
+        Ok(build_response(status, headers, body))
+    }
+}
+
+/// Serves the responses recorded by [`RecordingFetch`] without touching the network.
+#[derive(Clone, Debug)]
+pub struct ReplayFetch {
+    dir: Arc<PathBuf>,
+    sequence: Arc<Sequence>,
+}
+
+impl ReplayFetch {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: Arc::new(dir.into()),
+            sequence: Default::default(),
+        }
+    }
+
+    fn load(&self, name: &str) -> Option<(StatusCode, HeaderMap, Bytes)> {
+        let exchange = fs::read(self.dir.join(format!("{name}.json"))).ok()?;
+        let exchange = serde_json::from_slice::<RecordedExchange>(&exchange).ok()?;
+        let body = fs::read(self.dir.join(format!("{name}.body"))).ok()?;
+        let mut headers = HeaderMap::new();
+        if let Some(content_type) = exchange.content_type {
+            headers.insert(CONTENT_TYPE, content_type.try_into().ok()?);
+        }
+        let status = StatusCode::from_u16(exchange.status).ok()?;
+        Some((status, headers, body.into()))
+    }
+}
+
+#[async_trait]
+impl Fetch for ReplayFetch {
+    async fn fetch(&self, request: Request) -> Result<Response, reqwest::Error> {
+        let name = self.sequence.name(&request);
+        let (status, headers, body) = self.load(&name).unwrap_or_else(|| {
+            let error = serde_json::json!({
+                "code": error_codes::BAD_REQUEST,
+                "msg": format!("no recording {name} for {} {}", request.method(), request.url()),
+            });
+            let headers = HeaderMap::from_iter([(
+                CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            )]);
+            (StatusCode::NOT_FOUND, headers, error.to_string().into())
+        });
+        Ok(build_response(status, headers, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers `/fail` with a server error and everything else with an empty success.
+    #[derive(Clone, Debug)]
+    struct StatusFetch;
+
+    #[async_trait]
+    impl Fetch for StatusFetch {
+        async fn fetch(&self, request: Request) -> Result<Response, reqwest::Error> {
+            let status = match request.url().path() {
+                "/fail" => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::OK,
+            };
+            Ok(build_response(status, HeaderMap::new(), Bytes::new()))
+        }
+    }
+
+    fn get(path: &str) -> Request {
+        let url = format!("http://localhost/{path}").parse().unwrap();
+        Request::new(reqwest::Method::GET, url)
+    }
+
+    #[tokio::test]
+    async fn counts_requests_per_endpoint() {
+        let fetch = MetricsFetch::new(StatusFetch, Arc::new(Metrics::new()));
+        for path in ["about", "about", "fail", "sendGroupMessage"] {
+            fetch.fetch(get(path)).await.unwrap();
+        }
+        let snapshot = fetch.metrics().snapshot();
+        let counts = |endpoint: &str| {
+            let metrics = snapshot[endpoint];
+            (metrics.requests, metrics.errors)
+        };
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(counts("about"), (2, 0));
+        assert_eq!(counts("fail"), (1, 1));
+        assert_eq!(counts("sendGroupMessage"), (1, 0));
+        assert!(snapshot["about"].p50.is_some());
+    }
+
+    #[test]
+    fn computes_latency_percentiles() {
+        let metrics = Metrics::new();
+        assert!(metrics.snapshot().is_empty());
+        // recorded out of order
+        for ms in (1..=100).rev() {
+            metrics.record("about", Duration::from_millis(ms), true);
+        }
+        let about = metrics.snapshot()["about"];
+        assert_eq!(about.p50, Some(Duration::from_millis(50)));
+        assert_eq!(about.p99, Some(Duration::from_millis(99)));
+    }
+
+    #[test]
+    fn evicts_latencies_beyond_window() {
+        let metrics = Metrics::new();
+        let slow = Duration::from_secs(3600);
+        let fast = Duration::from_millis(1);
+        for _ in 0..LATENCY_SAMPLES {
+            metrics.record("about", slow, false);
+        }
+        for _ in 1..LATENCY_SAMPLES / 2 {
+            metrics.record("about", fast, true);
+        }
+        assert_eq!(metrics.snapshot()["about"].p50, Some(slow));
+        // fast requests now make up half of the window, which would not be the case had the slow
+        // ones all been kept
+        metrics.record("about", fast, true);
+        let about = metrics.snapshot()["about"];
+        assert_eq!(about.p50, Some(fast));
+        assert_eq!(about.p99, Some(slow));
+        assert_eq!(about.requests, LATENCY_SAMPLES as u64 * 3 / 2);
+        assert_eq!(about.errors, LATENCY_SAMPLES as u64);
+    }
+}
@@ -1,34 +1,45 @@
 #![forbid(unsafe_code)]
 
 pub mod fetch;
+pub mod supervisor;
 
 use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fmt::Debug;
-use std::num::NonZeroU32;
-use std::sync::LazyLock;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::ops::Deref;
+use std::sync::{Arc, LazyLock, Mutex};
 
 use async_trait::async_trait;
-use mah_core::adapter::{self, Bytes, Mah, MahSession};
-use mah_core::event::MessageOrEvent;
-use mah_core::message::Message;
+use futures_util::stream::{BoxStream, Stream, TryStreamExt as _};
+use mah_core::adapter::{self, error_codes, Bytes, Mah, MahSession};
+use mah_core::event::{Event, MessageOrEvent, RawMessageOrEvent};
+use mah_core::message::{self, DecodeOptions, Message};
 use mah_core::{
     types, AnnouncementDetails, Command, FileDetails, FileUpload, FriendDetails, GroupConfig,
-    GroupDetails, ImageInfo, MemberDetails, MemberInfo, Profile, ShortVideoInfo, VoiceInfo,
+    GroupDetails, ImageInfo, MemberDetails, MemberInfo, Profile, ShortVideoInfo, UserDetails,
+    VoiceInfo,
 };
-use reqwest::header::HeaderValue;
+use reqwest::header::USER_AGENT;
+pub use reqwest::header::{HeaderMap, HeaderValue};
 pub use reqwest::Url;
-use reqwest::{multipart, Method, Request, RequestBuilder, Response};
+use reqwest::{multipart, Body, IntoUrl, Method, Request, RequestBuilder, Response};
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use thiserror::Error;
 use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::{SendError, SendTimeoutError};
 pub use tokio::time::Duration;
+use tokio::time::{timeout_at, Instant};
 
 use self::fetch::{DefaultFetch, Fetch};
 
 #[async_trait]
 trait HttpAdapterHandler {
     fn base_url(&self) -> &Url;
+    fn headers(&self) -> &HeaderMap;
+    fn timeout(&self) -> Option<Duration>;
+    fn strict_decoding(&self) -> bool;
 
     fn get(&self, path: &str) -> RequestBuilder {
         self.request(Method::GET, path)
@@ -40,41 +51,58 @@ trait HttpAdapterHandler {
 
     fn request(&self, method: Method, path: &str) -> RequestBuilder {
         static REQUEST_BUILDER: LazyLock<reqwest::Client> = LazyLock::new(Default::default);
-        REQUEST_BUILDER.request(method, self.base_url().join(path).unwrap())
+        let builder = REQUEST_BUILDER
+            .request(method, self.base_url().join(path).unwrap())
+            .headers(self.headers().clone());
+        match self.timeout() {
+            Some(timeout) => builder.timeout(timeout),
+            None => builder,
+        }
     }
 
     async fn fetch(&self, request: Request) -> Result<Response, reqwest::Error>;
 
     async fn validate<T: DeserializeOwned>(&self, request: Request) -> Result<T, HttpAdapterError> {
-        let value = self
-            .fetch(request)
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
-        if let Ok(err) = adapter::Error::deserialize(&value) {
-            return Err(err.into());
-        }
-        Ok(T::deserialize(value)?)
+        validate_response(self.fetch(request).await?, self.strict_decoding()).await
     }
 
     async fn data<T: DeserializeOwned>(&self, request: Request) -> Result<T, HttpAdapterError> {
-        #[derive(Debug, Deserialize)]
-        struct Data<T> {
-            data: T,
-        }
-
         self.validate(request).await.map(|Data { data }| data)
     }
 
+    // only a failure while the request is being sent can cut the upload short
+    async fn upload<T: DeserializeOwned>(&self, request: Request) -> Result<T, HttpAdapterError> {
+        let response = self.fetch(request).await.map_err(|err| {
+            if err.is_body() || (err.is_request() && !err.is_connect()) {
+                HttpAdapterError::UploadInterrupted(err)
+            } else {
+                err.into()
+            }
+        })?;
+        validate_response(response, self.strict_decoding())
+            .await
+            .map(|Data { data }| data)
+    }
+
     async fn send(&self, request: Request) -> Result<i32, HttpAdapterError> {
         types::SendMessageResult::into(self.validate(request).await?)
     }
+
+    async fn send_detailed(
+        &self,
+        request: Request,
+    ) -> Result<types::SendMessageDetails, HttpAdapterError> {
+        types::SendMessageResult::into(self.validate(request).await?)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct HttpAdapter<F = DefaultFetch> {
     verify_key: String,
     base_url: Url,
+    headers: HeaderMap,
+    timeout: Option<Duration>,
+    strict_decoding: bool,
     fetch: F,
 }
 
@@ -96,10 +124,35 @@ impl<F: Fetch> HttpAdapter<F> {
         Self {
             verify_key: verify_key.unwrap_or_default(),
             base_url,
+            headers: HeaderMap::new(),
+            timeout: None,
+            strict_decoding: false,
             fetch,
         }
     }
 
+    pub fn headers(self, headers: HeaderMap) -> Self {
+        Self { headers, ..self }
+    }
+
+    pub fn user_agent(mut self, user_agent: HeaderValue) -> Self {
+        self.headers.insert(USER_AGENT, user_agent);
+        self
+    }
+
+    pub fn timeout(self, timeout: Option<Duration>) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Rejects responses with message chains containing more than one `Source` or `Quote` node,
+    /// which are otherwise decoded keeping the first of each. See [`DecodeOptions::strict`].
+    pub fn strict_decoding(self, strict_decoding: bool) -> Self {
+        Self {
+            strict_decoding,
+            ..self
+        }
+    }
+
     // region: verify
     pub async fn verify(&self) -> Result<HttpAdapterSession<F>, HttpAdapterError> {
         self.validate(
@@ -110,6 +163,7 @@ impl<F: Fetch> HttpAdapter<F> {
                 .build()?,
         )
         .await
+        .map_err(setup_error)
         .map(|types::VerifyResult { session }| HttpAdapterSession {
             session_key: {
                 let mut value = HeaderValue::from_str(&session).unwrap();
@@ -118,8 +172,31 @@ impl<F: Fetch> HttpAdapter<F> {
             },
             fetch: self.fetch.clone(),
             base_url: self.base_url.clone(),
+            headers: self.headers.clone(),
+            timeout: self.timeout,
+            strict_decoding: self.strict_decoding,
+            bot_user: Default::default(),
         })
     }
+
+    pub async fn verify_and_bind(
+        &self,
+        qq: i64,
+    ) -> Result<HttpAdapterSession<F>, HttpAdapterError> {
+        let session = self.verify().await?;
+        session.bind(&types::BindArgs { qq }).await?;
+        Ok(session)
+    }
+
+    pub async fn verify_and_bind_all(
+        &self,
+    ) -> Result<Vec<(i64, HttpAdapterSession<F>)>, HttpAdapterError> {
+        let mut sessions = Vec::new();
+        for qq in self.get_bots_list().await? {
+            sessions.push((qq, self.verify_and_bind(qq).await?));
+        }
+        Ok(sessions)
+    }
     // endregion
 }
 
@@ -144,6 +221,18 @@ impl<F: Fetch> HttpAdapterHandler for HttpAdapter<F> {
         &self.base_url
     }
 
+    fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn strict_decoding(&self) -> bool {
+        self.strict_decoding
+    }
+
     async fn fetch(&self, request: Request) -> Result<Response, reqwest::Error> {
         self.fetch.fetch(request).await
     }
@@ -152,14 +241,20 @@ impl<F: Fetch> HttpAdapterHandler for HttpAdapter<F> {
 #[derive(Clone, Debug)]
 pub struct HttpAdapterSession<F = DefaultFetch> {
     base_url: Url,
+    headers: HeaderMap,
+    timeout: Option<Duration>,
+    strict_decoding: bool,
     session_key: HeaderValue,
     fetch: F,
+    bot_user: Arc<Mutex<Option<UserDetails>>>,
 }
 
 impl<F: Fetch> HttpAdapterSession<F> {
     // region: verify
     pub async fn bind(&self, args: &types::BindArgs) -> Result<(), HttpAdapterError> {
-        self.validate(self.post("bind").json(args).build()?).await
+        self.validate(self.post("bind").json(args).build()?)
+            .await
+            .map_err(setup_error)
     }
 
     pub async fn release(&self, args: &types::BindArgs) -> Result<(), HttpAdapterError> {
@@ -168,6 +263,73 @@ impl<F: Fetch> HttpAdapterSession<F> {
     }
     // endregion
 
+    pub fn with_timeout(&self, timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            ..self.clone()
+        }
+    }
+
+    pub async fn ping(&self) -> Result<(), HttpAdapterError> {
+        match self.count_message().await {
+            Ok(_) => Ok(()),
+            Err(HttpAdapterError::Mirai(err))
+                if matches!(
+                    err.code.get(),
+                    error_codes::INVALID_SESSION | error_codes::UNVERIFIED_SESSION
+                ) =>
+            {
+                Err(HttpAdapterError::InvalidSession(err))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub async fn download(
+        &self,
+        url: impl IntoUrl,
+        max_bytes: usize,
+    ) -> Result<Bytes, HttpAdapterError> {
+        let mut response = self.open(url).await?;
+        if response
+            .content_length()
+            .is_some_and(|len| len > max_bytes as u64)
+        {
+            return Err(HttpAdapterError::TooLarge(max_bytes));
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > max_bytes {
+                return Err(HttpAdapterError::TooLarge(max_bytes));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body.into())
+    }
+
+    /// Like [`Self::download`], but hands out the body as it arrives without a size limit, e.g. for
+    /// [`FileHandle::copy_to`](mah_core::FileHandle::copy_to) and
+    /// [`IncomingShortVideoNode::download`](mah_core::message::IncomingShortVideoNode::download),
+    /// which apply their own.
+    pub async fn download_stream(
+        &self,
+        url: impl IntoUrl,
+    ) -> Result<
+        impl Stream<Item = Result<Bytes, HttpAdapterError>> + Send + 'static,
+        HttpAdapterError,
+    > {
+        Ok(self.open(url).await?.bytes_stream().map_err(Into::into))
+    }
+
+    // mirai's download urls are not under the api endpoint and must not receive the session key
+    async fn open(&self, url: impl IntoUrl) -> Result<Response, HttpAdapterError> {
+        Ok(self
+            .fetch
+            .fetch(Request::new(Method::GET, url.into_url()?))
+            .await?
+            .error_for_status()?)
+    }
+
     // region: message
     pub async fn count_message(&self) -> Result<i32, HttpAdapterError> {
         self.data(self.get("countMessage").build()?).await
@@ -177,15 +339,26 @@ impl<F: Fetch> HttpAdapterSession<F> {
         &self,
         args: &types::CountArgs,
     ) -> Result<Vec<MessageOrEvent>, HttpAdapterError> {
-        self.data(self.get("fetchMessage").query(args).build()?)
+        self.events(self.get("fetchMessage").query(args).build()?)
             .await
     }
 
+    pub async fn fetch_message_raw(
+        &self,
+        args: &types::CountArgs,
+    ) -> Result<Vec<RawMessageOrEvent>, HttpAdapterError> {
+        let events: Vec<RawMessageOrEvent> = self
+            .data(self.get("fetchMessage").query(args).build()?)
+            .await?;
+        self.observe_events(events.iter().map(|event| &event.event));
+        Ok(events)
+    }
+
     pub async fn fetch_latest_message(
         &self,
         args: &types::CountArgs,
     ) -> Result<Vec<MessageOrEvent>, HttpAdapterError> {
-        self.data(self.get("fetchLatestMessage").query(args).build()?)
+        self.events(self.get("fetchLatestMessage").query(args).build()?)
             .await
     }
 
@@ -193,7 +366,7 @@ impl<F: Fetch> HttpAdapterSession<F> {
         &self,
         args: &types::CountArgs,
     ) -> Result<Vec<MessageOrEvent>, HttpAdapterError> {
-        self.data(self.get("peekMessage").query(args).build()?)
+        self.events(self.get("peekMessage").query(args).build()?)
             .await
     }
 
@@ -201,12 +374,99 @@ impl<F: Fetch> HttpAdapterSession<F> {
         &self,
         args: &types::CountArgs,
     ) -> Result<Vec<MessageOrEvent>, HttpAdapterError> {
-        self.data(self.get("peekLatestMessage").query(args).build()?)
+        self.events(self.get("peekLatestMessage").query(args).build()?)
             .await
     }
+
+    async fn events(&self, request: Request) -> Result<Vec<MessageOrEvent>, HttpAdapterError> {
+        let events: Vec<MessageOrEvent> = self.data(request).await?;
+        self.observe_events(&events);
+        Ok(events)
+    }
+
+    fn observe_events<'a>(&self, events: impl IntoIterator<Item = &'a MessageOrEvent>) {
+        if events.into_iter().any(|event| {
+            matches!(
+                event,
+                MessageOrEvent::Event(Event::BotOnline(_) | Event::BotRelogin(_))
+            )
+        }) {
+            *self.bot_user.lock().unwrap() = None;
+        }
+    }
+
+    pub async fn drain_all(&self, max: usize) -> Result<Vec<MessageOrEvent>, HttpAdapterError> {
+        let mut events = Vec::new();
+        while events.len() < max {
+            let remaining = i32::try_from(max - events.len()).unwrap_or(i32::MAX);
+            let count = self.count_message().await?.min(remaining);
+            if count <= 0 {
+                break;
+            }
+            let batch = self
+                .fetch_message(&types::CountArgs {
+                    count: NonZeroU32::new(count as u32),
+                })
+                .await?;
+            if batch.is_empty() {
+                break;
+            }
+            events.extend(batch);
+        }
+        Ok(events)
+    }
     // endregion
 }
 
+impl<F> AsRef<HttpAdapterSession<F>> for HttpAdapterSession<F> {
+    fn as_ref(&self) -> &HttpAdapterSession<F> {
+        self
+    }
+}
+
+/// A session shared between the event poller and any number of handler tasks. Cloning it is cheap
+/// and all clones use the same session key.
+#[derive(Debug)]
+pub struct SharedSession<F = DefaultFetch>(Arc<HttpAdapterSession<F>>);
+
+impl<F> SharedSession<F> {
+    pub fn new(session: HttpAdapterSession<F>) -> Self {
+        Self(Arc::new(session))
+    }
+}
+
+impl<F> Clone for SharedSession<F> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<F> Deref for SharedSession<F> {
+    type Target = HttpAdapterSession<F>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<F> AsRef<HttpAdapterSession<F>> for SharedSession<F> {
+    fn as_ref(&self) -> &HttpAdapterSession<F> {
+        &self.0
+    }
+}
+
+impl<F> From<HttpAdapterSession<F>> for SharedSession<F> {
+    fn from(session: HttpAdapterSession<F>) -> Self {
+        Self::new(session)
+    }
+}
+
+impl<F> From<Arc<HttpAdapterSession<F>>> for SharedSession<F> {
+    fn from(session: Arc<HttpAdapterSession<F>>) -> Self {
+        Self(session)
+    }
+}
+
 #[async_trait]
 impl<F: Fetch> MahSession for HttpAdapterSession<F> {
     type Error = HttpAdapterError;
@@ -238,6 +498,30 @@ impl<F: Fetch> MahSession for HttpAdapterSession<F> {
             .await
     }
 
+    async fn send_friend_message_detailed(
+        &self,
+        args: &types::SendMessageArgs,
+    ) -> Result<types::SendMessageDetails, Self::Error> {
+        self.send_detailed(self.post("sendFriendMessage").json(args).build()?)
+            .await
+    }
+
+    async fn send_group_message_detailed(
+        &self,
+        args: &types::SendMessageArgs,
+    ) -> Result<types::SendMessageDetails, Self::Error> {
+        self.send_detailed(self.post("sendGroupMessage").json(args).build()?)
+            .await
+    }
+
+    async fn send_temp_message_detailed(
+        &self,
+        args: &types::SendTempMessageArgs,
+    ) -> Result<types::SendMessageDetails, Self::Error> {
+        self.send_detailed(self.post("sendTempMessage").json(args).build()?)
+            .await
+    }
+
     async fn send_other_client_message(
         &self,
         args: &types::SendMessageArgs,
@@ -246,6 +530,30 @@ impl<F: Fetch> MahSession for HttpAdapterSession<F> {
             .await
     }
 
+    async fn send_raw_friend_message(
+        &self,
+        args: &types::SendRawMessageArgs,
+    ) -> Result<i32, Self::Error> {
+        self.send(self.post("sendFriendMessage").json(args).build()?)
+            .await
+    }
+
+    async fn send_raw_group_message(
+        &self,
+        args: &types::SendRawMessageArgs,
+    ) -> Result<i32, Self::Error> {
+        self.send(self.post("sendGroupMessage").json(args).build()?)
+            .await
+    }
+
+    async fn send_raw_temp_message(
+        &self,
+        args: &types::SendRawTempMessageArgs,
+    ) -> Result<i32, Self::Error> {
+        self.send(self.post("sendTempMessage").json(args).build()?)
+            .await
+    }
+
     async fn upload_image(
         &self,
         media_type: types::MediaType,
@@ -254,7 +562,10 @@ impl<F: Fetch> MahSession for HttpAdapterSession<F> {
         let form = multipart::Form::new().text("type", <&'static str>::from(media_type));
         let form = match image {
             FileUpload::Url(url) => form.text("url", url),
-            FileUpload::Bytes(bytes) => form.part("img", multipart::Part::stream(bytes)),
+            FileUpload::Bytes(bytes) => {
+                check_upload_size(bytes.len(), self.media_limits().image)?;
+                form.part("img", multipart::Part::stream(bytes))
+            }
         };
         self.validate(self.post("uploadImage").multipart(form).build()?)
             .await
@@ -268,7 +579,10 @@ impl<F: Fetch> MahSession for HttpAdapterSession<F> {
         let form = multipart::Form::new().text("type", <&'static str>::from(media_type));
         let form = match voice {
             FileUpload::Url(url) => form.text("url", url),
-            FileUpload::Bytes(bytes) => form.part("voice", multipart::Part::stream(bytes)),
+            FileUpload::Bytes(bytes) => {
+                check_upload_size(bytes.len(), self.media_limits().voice)?;
+                form.part("voice", multipart::Part::stream(bytes))
+            }
         };
         self.validate(self.post("uploadVoice").multipart(form).build()?)
             .await
@@ -280,6 +594,7 @@ impl<F: Fetch> MahSession for HttpAdapterSession<F> {
         video: Bytes,
         thumbnail: Bytes,
     ) -> Result<ShortVideoInfo, Self::Error> {
+        check_upload_size(video.len(), self.media_limits().short_video)?;
         self.validate(
             self.post("uploadShortVideo")
                 .multipart(
@@ -467,6 +782,15 @@ impl<F: Fetch> MahSession for HttpAdapterSession<F> {
     async fn get_session_info(&self) -> Result<types::GetSessionInfoResult, Self::Error> {
         self.data(self.get("sessionInfo").build()?).await
     }
+
+    async fn cached_bot_user(&self) -> Result<UserDetails, Self::Error> {
+        if let Some(user) = self.bot_user.lock().unwrap().clone() {
+            return Ok(user);
+        }
+        let user = self.get_session_info().await?.qq;
+        *self.bot_user.lock().unwrap() = Some(user.clone());
+        Ok(user)
+    }
     // endregion
 
     // region: file
@@ -492,7 +816,7 @@ impl<F: Fetch> MahSession for HttpAdapterSession<F> {
         name: Cow<'static, str>,
         file: Bytes,
     ) -> Result<FileDetails, Self::Error> {
-        self.data(
+        self.upload(
             self.post("file/upload")
                 .multipart(
                     multipart::Form::new()
@@ -506,6 +830,29 @@ impl<F: Fetch> MahSession for HttpAdapterSession<F> {
         .await
     }
 
+    async fn upload_file_stream(
+        &self,
+        group: i64,
+        path: Cow<'static, str>,
+        name: Cow<'static, str>,
+        size: u64,
+        file: BoxStream<'static, Result<Bytes, Self::Error>>,
+    ) -> Result<FileDetails, Self::Error> {
+        let file = multipart::Part::stream_with_length(Body::wrap_stream(file), size);
+        self.upload(
+            self.post("file/upload")
+                .multipart(
+                    multipart::Form::new()
+                        .text("path", path)
+                        .text("type", "group")
+                        .text("target", group.to_string())
+                        .part("file", file.file_name(name)),
+                )
+                .build()?,
+        )
+        .await
+    }
+
     async fn delete_file(&self, args: &types::FileArgs) -> Result<(), Self::Error> {
         self.validate(self.post("file/delete").json(args).build()?)
             .await
@@ -563,6 +910,18 @@ impl<F: Fetch> HttpAdapterHandler for HttpAdapterSession<F> {
         &self.base_url
     }
 
+    fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn strict_decoding(&self) -> bool {
+        self.strict_decoding
+    }
+
     async fn fetch(&self, mut request: Request) -> Result<Response, reqwest::Error> {
         request
             .headers_mut()
@@ -576,6 +935,9 @@ pub struct HttpAdapterEvents {
     buffer: usize,
     batch_size: Option<NonZeroU32>,
     poll_interval: Duration,
+    overflow: OverflowPolicy,
+    stall_threshold: Option<Duration>,
+    timeout: Option<Duration>,
 }
 
 impl HttpAdapterEvents {
@@ -584,6 +946,9 @@ impl HttpAdapterEvents {
             buffer: 1,
             batch_size: None,
             poll_interval: Duration::from_millis(50),
+            overflow: OverflowPolicy::Block,
+            stall_threshold: None,
+            timeout: None,
         }
     }
 
@@ -605,58 +970,913 @@ impl HttpAdapterEvents {
         }
     }
 
+    pub fn overflow(self, overflow: OverflowPolicy) -> Self {
+        Self { overflow, ..self }
+    }
+
+    pub fn stall_threshold(self, stall_threshold: Option<Duration>) -> Self {
+        Self {
+            stall_threshold,
+            ..self
+        }
+    }
+
+    pub fn timeout(self, timeout: Option<Duration>) -> Self {
+        Self { timeout, ..self }
+    }
+
+    pub fn batch_delivery(
+        self,
+        count: NonZeroUsize,
+        max_wait: Duration,
+    ) -> BatchedHttpAdapterEvents {
+        BatchedHttpAdapterEvents {
+            events: self,
+            count,
+            max_wait,
+        }
+    }
+
+    /// Polls `fetchMessage` in the background and forwards the events to the returned receiver.
+    ///
+    /// Fetching removes events from mirai's queue, so events that have been fetched but not yet
+    /// handed to the channel when it closes are passed to `on_error` as
+    /// [`HttpAdapterError::Undelivered`] instead of being lost silently. Events already in the
+    /// channel are lost when the receiver is dropped; to shut down without losing any, call
+    /// [`mpsc::Receiver::close`] and keep receiving until it returns `None`.
+    ///
+    /// There is deliberately no option to filter by account: `fetchMessage` takes no `qq` and only
+    /// returns events for the bot the session is bound to, so events of different bots never mix.
+    /// To listen to several accounts, call this once for each session returned by
+    /// [`HttpAdapter::verify_and_bind_all`].
     pub fn listen<F: Fetch>(
         self,
         session: impl AsRef<HttpAdapterSession<F>> + Send + 'static,
-        mut on_error: impl FnMut(HttpAdapterError) + Send + 'static,
+        on_error: impl FnMut(HttpAdapterError) -> PollAction + Send + 'static,
     ) -> mpsc::Receiver<MessageOrEvent> {
+        self.poll(session, on_error)
+    }
+
+    /// Like [`HttpAdapterEvents::listen`], but also keeps the JSON of each event as received.
+    pub fn listen_raw<F: Fetch>(
+        self,
+        session: impl AsRef<HttpAdapterSession<F>> + Send + 'static,
+        on_error: impl FnMut(HttpAdapterError) -> PollAction + Send + 'static,
+    ) -> mpsc::Receiver<RawMessageOrEvent> {
+        self.poll(session, on_error)
+    }
+
+    fn poll<T: PolledEvent, F: Fetch>(
+        self,
+        session: impl AsRef<HttpAdapterSession<F>> + Send + 'static,
+        mut on_error: impl FnMut(HttpAdapterError) -> PollAction + Send + 'static,
+    ) -> mpsc::Receiver<T> {
         let (tx, rx) = mpsc::channel(self.buffer);
         let args = types::CountArgs {
             count: self.batch_size,
         };
-        let poll_interval = self.poll_interval;
+        let Self {
+            buffer,
+            poll_interval,
+            overflow,
+            stall_threshold,
+            timeout,
+            ..
+        } = self;
         tokio::spawn(async move {
-            let session = session.as_ref();
-            loop {
+            let session = match timeout {
+                Some(timeout) => session.as_ref().with_timeout(Some(timeout)),
+                None => session.as_ref().clone(),
+            };
+            let mut pending = VecDeque::new();
+            'poll: loop {
                 let events = loop {
-                    match session.fetch_message(&args).await {
+                    flush_pending(&tx, &mut pending);
+                    match T::fetch(&session, &args).await {
                         Ok(events) => {
                             if !events.is_empty() {
                                 break events;
                             }
                         }
-                        Err(err) => {
-                            on_error(err);
-                        }
+                        Err(err) => match on_error(err) {
+                            PollAction::Continue => {}
+                            PollAction::Backoff(duration) => {
+                                if tokio::time::timeout(duration, tx.closed()).await.is_ok() {
+                                    break 'poll;
+                                }
+                            }
+                            PollAction::Stop => break 'poll,
+                        },
                     }
                     if tokio::time::timeout(poll_interval, tx.closed())
                         .await
                         .is_ok()
                     {
-                        return;
+                        break 'poll;
                     }
                 };
-                for event in events {
-                    let _ = tx.send(event).await;
+                match overflow {
+                    OverflowPolicy::Block => {
+                        let mut events = events.into_iter();
+                        while let Some(mut event) = events.next() {
+                            if let Some(threshold) = stall_threshold {
+                                match tx.send_timeout(event, threshold).await {
+                                    Ok(()) => continue,
+                                    Err(SendTimeoutError::Timeout(value)) => event = value,
+                                    Err(SendTimeoutError::Closed(value)) => {
+                                        pending.push_back(value);
+                                        pending.extend(events);
+                                        break 'poll;
+                                    }
+                                }
+                                let err = HttpAdapterError::ConsumerStalled(threshold);
+                                if matches!(on_error(err), PollAction::Stop) {
+                                    pending.push_back(event);
+                                    pending.extend(events);
+                                    break 'poll;
+                                }
+                            }
+                            if let Err(SendError(value)) = tx.send(event).await {
+                                pending.push_back(value);
+                                pending.extend(events);
+                                break 'poll;
+                            }
+                        }
+                    }
+                    OverflowPolicy::DropOldest => {
+                        pending.extend(events);
+                        if tx.is_closed() {
+                            break 'poll;
+                        }
+                        flush_pending(&tx, &mut pending);
+                        let dropped = pending.len().saturating_sub(buffer);
+                        if dropped != 0 {
+                            pending.drain(..dropped);
+                            let err = HttpAdapterError::EventsDropped(dropped);
+                            if matches!(on_error(err), PollAction::Stop) {
+                                break 'poll;
+                            }
+                        }
+                    }
                 }
             }
+            if !pending.is_empty() {
+                on_error(HttpAdapterError::Undelivered(
+                    pending.into_iter().map(T::into_event).collect(),
+                ));
+            }
         });
         rx
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct BatchedHttpAdapterEvents {
+    events: HttpAdapterEvents,
+    count: NonZeroUsize,
+    max_wait: Duration,
+}
+
+impl BatchedHttpAdapterEvents {
+    pub fn listen<F: Fetch>(
+        self,
+        session: impl AsRef<HttpAdapterSession<F>> + Send + 'static,
+        on_error: impl FnMut(HttpAdapterError) -> PollAction + Send + 'static,
+    ) -> mpsc::Receiver<Vec<MessageOrEvent>> {
+        let (tx, rx) = mpsc::channel(self.events.buffer);
+        let on_error = Arc::new(Mutex::new(on_error));
+        let mut events = self.events.listen(session, {
+            let on_error = on_error.clone();
+            move |err| (*on_error.lock().unwrap())(err)
+        });
+        let Self {
+            count, max_wait, ..
+        } = self;
+        tokio::spawn(async move {
+            let mut undelivered = loop {
+                let event = tokio::select! {
+                    biased;
+                    () = tx.closed() => break Vec::new(),
+                    event = events.recv() => event,
+                };
+                let Some(event) = event else {
+                    return;
+                };
+                let deadline = Instant::now() + max_wait;
+                let mut batch = vec![event];
+                let mut done = false;
+                while batch.len() < count.get() {
+                    match timeout_at(deadline, events.recv()).await {
+                        Ok(Some(event)) => batch.push(event),
+                        Ok(None) => {
+                            done = true;
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                if let Err(SendError(batch)) = tx.send(batch).await {
+                    break batch;
+                }
+                if done {
+                    return;
+                }
+            };
+            events.close();
+            while let Some(event) = events.recv().await {
+                undelivered.push(event);
+            }
+            if !undelivered.is_empty() {
+                (*on_error.lock().unwrap())(HttpAdapterError::Undelivered(undelivered));
+            }
+        });
+        rx
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Data<T> {
+    data: T,
+}
+
+async fn validate_response<T: DeserializeOwned>(
+    response: Response,
+    strict_decoding: bool,
+) -> Result<T, HttpAdapterError> {
+    let body = response.bytes().await?;
+    let value = if body.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_slice(&body)?
+    };
+    if let Ok(err) = adapter::Error::deserialize(&value) {
+        return Err(err.into());
+    }
+    // Endpoints without data, such as `()` ones, answer with just a status, or with an empty body
+    // behind some proxies.
+    if let Ok(unit) = T::deserialize(serde_json::Value::Null) {
+        return Ok(unit);
+    }
+    if body.is_empty() {
+        return Err(HttpAdapterError::EmptyBody);
+    }
+    let options = DecodeOptions {
+        strict: strict_decoding,
+    };
+    Ok(message::decode(value, options)?)
+}
+
+fn setup_error(err: HttpAdapterError) -> HttpAdapterError {
+    match err {
+        HttpAdapterError::Mirai(err) if err.code.get() == error_codes::WRONG_VERIFY_KEY => {
+            HttpAdapterError::WrongVerifyKey(err)
+        }
+        HttpAdapterError::Mirai(err) if err.code.get() == error_codes::BOT_NOT_FOUND => {
+            HttpAdapterError::BotNotFound(err)
+        }
+        err => err,
+    }
+}
+
+fn check_upload_size(size: usize, limit: usize) -> Result<(), HttpAdapterError> {
+    if size > limit {
+        return Err(HttpAdapterError::UploadTooLarge { size, limit });
+    }
+    Ok(())
+}
+
+#[async_trait]
+trait PolledEvent: Send + Sized + 'static {
+    async fn fetch<F: Fetch>(
+        session: &HttpAdapterSession<F>,
+        args: &types::CountArgs,
+    ) -> Result<Vec<Self>, HttpAdapterError>;
+    fn into_event(self) -> MessageOrEvent;
+}
+
+#[async_trait]
+impl PolledEvent for MessageOrEvent {
+    async fn fetch<F: Fetch>(
+        session: &HttpAdapterSession<F>,
+        args: &types::CountArgs,
+    ) -> Result<Vec<Self>, HttpAdapterError> {
+        session.fetch_message(args).await
+    }
+
+    fn into_event(self) -> MessageOrEvent {
+        self
+    }
+}
+
+#[async_trait]
+impl PolledEvent for RawMessageOrEvent {
+    async fn fetch<F: Fetch>(
+        session: &HttpAdapterSession<F>,
+        args: &types::CountArgs,
+    ) -> Result<Vec<Self>, HttpAdapterError> {
+        session.fetch_message_raw(args).await
+    }
+
+    fn into_event(self) -> MessageOrEvent {
+        self.event
+    }
+}
+
+fn flush_pending<T>(tx: &mpsc::Sender<T>, pending: &mut VecDeque<T>) {
+    while !pending.is_empty() {
+        let Ok(permit) = tx.try_reserve() else {
+            break;
+        };
+        permit.send(pending.pop_front().unwrap());
+    }
+}
+
 impl Default for HttpAdapterEvents {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    Block,
+    DropOldest,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PollAction {
+    Continue,
+    Backoff(Duration),
+    Stop,
+}
+
 #[derive(Debug, Error)]
 pub enum HttpAdapterError {
+    #[error("failed to build request: {0}")]
+    Build(reqwest::Error),
     #[error("failed to fetch: {0}")]
-    Fetch(#[from] reqwest::Error),
+    Fetch(reqwest::Error),
     #[error("invalid json: {0}")]
     Json(#[from] serde_json::Error),
     #[error("mirai error: {0}")]
     Mirai(#[from] adapter::Error),
+    #[error("response body is empty")]
+    EmptyBody,
+    #[error("upload interrupted: {0}")]
+    UploadInterrupted(reqwest::Error),
+    #[error("invalid session: {0}")]
+    InvalidSession(adapter::Error),
+    #[error("wrong verify key: {0}")]
+    WrongVerifyKey(adapter::Error),
+    #[error("bot is not logged in to mirai: {0}")]
+    BotNotFound(adapter::Error),
+    #[error("consumer has not received events for {0:?}")]
+    ConsumerStalled(Duration),
+    #[error("dropped {0} events because the consumer is too slow")]
+    EventsDropped(usize),
+    #[error("{} fetched events were not delivered", .0.len())]
+    Undelivered(Vec<MessageOrEvent>),
+    #[error("response body exceeds {0} bytes")]
+    TooLarge(usize),
+    #[error("upload of {size} bytes exceeds the limit of {limit} bytes")]
+    UploadTooLarge { size: usize, limit: usize },
+    #[error("{} not supported by mirai-api-http", .0 .0)]
+    Unsupported(#[from] adapter::Unsupported),
+}
+
+impl From<reqwest::Error> for HttpAdapterError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_builder() {
+            Self::Build(err)
+        } else {
+            Self::Fetch(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use futures_util::StreamExt as _;
+    use mah_core::event::{auto_handle_invites, InviteDecision};
+    use mah_core::message::AnyMessage as _;
+    use mah_core::CopyFileError;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+    use tokio::time::timeout;
+
+    use super::*;
+
+    // Serves the given responses in order, then empty event batches, logging the path and body of
+    // each request.
+    #[derive(Clone, Debug)]
+    struct ScriptedFetch {
+        responses: Arc<Mutex<VecDeque<(u16, &'static str)>>>,
+        requests: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    impl ScriptedFetch {
+        fn new(responses: impl IntoIterator<Item = (u16, &'static str)>) -> Self {
+            Self {
+                responses: Arc::new(Mutex::new(responses.into_iter().collect())),
+                requests: Default::default(),
+            }
+        }
+
+        fn requests(&self) -> Vec<(String, String)> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl Fetch for ScriptedFetch {
+        async fn fetch(&self, request: Request) -> Result<Response, reqwest::Error> {
+            let body = request.body().and_then(|body| body.as_bytes());
+            let body = String::from_utf8_lossy(body.unwrap_or_default()).into_owned();
+            let path = request.url().path().to_owned();
+            self.requests.lock().unwrap().push((path, body));
+            let (status, body) = self
+                .responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or((200, r#"{"code":0,"msg":"","data":[]}"#));
+            let response = http::Response::builder().status(status).body(body);
+            Ok(response.unwrap().into())
+        }
+    }
+
+    fn session<F: Fetch>(base_url: Url, fetch: F) -> HttpAdapterSession<F> {
+        HttpAdapterSession {
+            base_url,
+            headers: HeaderMap::new(),
+            timeout: None,
+            strict_decoding: false,
+            session_key: HeaderValue::from_static("session"),
+            fetch,
+            bot_user: Default::default(),
+        }
+    }
+
+    fn scripted_session(
+        responses: impl IntoIterator<Item = (u16, &'static str)>,
+    ) -> HttpAdapterSession<ScriptedFetch> {
+        let base_url = Url::parse("http://localhost/").unwrap();
+        session(base_url, ScriptedFetch::new(responses))
+    }
+
+    #[tokio::test]
+    async fn empty_body_is_success_without_data() {
+        let session = scripted_session([(200, ""), (204, "")]);
+        let args = types::BindArgs { qq: 10000 };
+        session.release(&args).await.unwrap();
+        session.release(&args).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn status_body_is_success_without_data() {
+        let session = scripted_session([(200, r#"{"code":0,"msg":"success"}"#)]);
+        session
+            .release(&types::BindArgs { qq: 10000 })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_time_is_taken_from_the_response_only() {
+        let session = scripted_session([
+            (200, r#"{"code":0,"msg":"success","messageId":1}"#),
+            (
+                200,
+                r#"{"code":0,"msg":"success","messageId":2,"time":1700000000}"#,
+            ),
+        ]);
+        let nodes = ["hello".into()];
+        let contents = mah_core::message::OutgoingMessageContents::new(&nodes);
+        let args = types::SendMessageArgs {
+            target: 987654321,
+            contents: &contents,
+        };
+        let details = session.send_group_message_detailed(&args).await.unwrap();
+        assert_eq!((details.message_id, details.time_secs), (1, None));
+        let details = session.send_group_message_detailed(&args).await.unwrap();
+        assert_eq!(
+            (details.message_id, details.time_secs),
+            (2, Some(1700000000))
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_body_is_an_error_with_data() {
+        let session = scripted_session([(200, "")]);
+        let result = session.get_friend_list().await;
+        assert!(
+            matches!(result, Err(HttpAdapterError::EmptyBody)),
+            "{result:?}"
+        );
+    }
+
+    fn invite(from_id: i64) -> MessageOrEvent {
+        serde_json::from_value(serde_json::json!({
+            "type": "BotInvitedJoinGroupRequestEvent",
+            "eventId": from_id * 10,
+            "fromId": from_id,
+            "nick": "Inviter",
+            "groupId": 987654321,
+            "groupName": "Group",
+            "message": "",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn auto_handle_invites_answers_invites_and_passes_the_rest() {
+        let session = scripted_session([
+            (200, r#"{"code":0,"msg":"success"}"#),
+            (200, r#"{"code":0,"msg":"success"}"#),
+            (200, r#"{"code":10,"msg":"no permission"}"#),
+        ]);
+        let events = serde_json::from_str::<Data<VecDeque<MessageOrEvent>>>(THREE_EVENTS);
+        let mut events = events.unwrap().data;
+        let events = [
+            invite(1),
+            events.pop_front().unwrap(),
+            invite(2),
+            events.pop_front().unwrap(),
+            invite(1),
+            events.pop_front().unwrap(),
+        ];
+        let mut decisions = Vec::new();
+        let passed = auto_handle_invites(
+            futures_util::stream::iter(events),
+            &session,
+            |request| request.from_id == 1,
+            |request, result| {
+                let result = result.map_err(|err| match err {
+                    HttpAdapterError::Mirai(err) => err.code.get(),
+                    err => panic!("unexpected {err}"),
+                });
+                decisions.push((request.event_id, result));
+            },
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(online_ids(passed), [1, 2, 3]);
+        assert_eq!(
+            decisions,
+            [
+                (10, Ok(InviteDecision::Accepted)),
+                (20, Ok(InviteDecision::Ignored)),
+                (10, Err(10)),
+            ]
+        );
+        let operations = session
+            .fetch
+            .requests()
+            .into_iter()
+            .map(|(path, body)| {
+                let body = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+                (path, body["operate"].as_i64().unwrap())
+            })
+            .collect::<Vec<_>>();
+        let path = "/resp/botInvitedJoinGroupRequestEvent".to_owned();
+        assert_eq!(
+            operations,
+            [(path.clone(), 0), (path.clone(), 1), (path, 0)]
+        );
+    }
+
+    const MESSAGE_TIME: i64 = 1700000000;
+
+    fn group_message(id: i32) -> serde_json::Value {
+        serde_json::json!({
+            "type": "GroupMessage",
+            "sender": {
+                "id": 123456789,
+                "memberName": "Alice",
+                "specialTitle": "",
+                "permission": "MEMBER",
+                "joinTimestamp": 1600000000,
+                "lastSpeakTimestamp": MESSAGE_TIME,
+                "muteTimeRemaining": 0,
+                "group": { "id": 987654321, "name": "Group", "permission": "ADMINISTRATOR" },
+            },
+            "messageChain": [
+                { "type": "Source", "id": id, "time": MESSAGE_TIME + i64::from(id) },
+                { "type": "Plain", "text": "hi" },
+            ],
+        })
+    }
+
+    fn data(data: serde_json::Value) -> &'static str {
+        let body = serde_json::json!({ "code": 0, "msg": "", "data": data });
+        body.to_string().leak()
+    }
+
+    // The half-width of each requested window of roaming messages.
+    fn roaming_spans(fetch: &ScriptedFetch) -> Vec<i64> {
+        fetch
+            .requests()
+            .into_iter()
+            .filter(|(path, _)| path == "/roamingMessages")
+            .map(|(_, body)| {
+                let args = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+                assert_eq!(args["group"], 987654321);
+                (args["timeEnd"].as_i64().unwrap() - args["timeStart"].as_i64().unwrap()) / 2
+            })
+            .collect()
+    }
+
+    fn message_ids(messages: &[Message]) -> Vec<Option<i32>> {
+        messages.iter().map(|message| message.id()).collect()
+    }
+
+    #[tokio::test]
+    async fn context_window_widens_until_enough_messages() {
+        let roaming = |ids: &[i32]| data(ids.iter().map(|&id| group_message(id)).collect());
+        let session = scripted_session([
+            (200, data(group_message(5))),
+            (200, roaming(&[4, 5])),
+            (200, roaming(&[5, 4, 6])),
+            (200, roaming(&[6, 3, 5, 7, 4])),
+        ]);
+        let handle = mah_core::Bot.get_message(5, 987654321);
+        let messages = handle.context_window(&session, 2, 1).await.unwrap();
+        assert_eq!(message_ids(&messages), [Some(3), Some(4), Some(5), Some(6)]);
+        assert_eq!(roaming_spans(&session.fetch), [600, 1200, 2400]);
+    }
+
+    #[tokio::test]
+    async fn context_window_gives_up_after_a_day() {
+        // roaming messages come back empty once the scripted responses run out
+        let session = scripted_session([(200, data(group_message(5)))]);
+        let handle = mah_core::Bot.get_message(5, 987654321);
+        let messages = handle.context_window(&session, 2, 1).await.unwrap();
+        assert_eq!(message_ids(&messages), [Some(5)]);
+        assert_eq!(
+            roaming_spans(&session.fetch),
+            [600, 1200, 2400, 4800, 9600, 19200, 38400, 76800, 86400]
+        );
+    }
+
+    const THREE_EVENTS: &str = r#"{"code":0,"msg":"","data":[
+        {"type":"BotOnlineEvent","qq":1},
+        {"type":"BotOnlineEvent","qq":2},
+        {"type":"BotOnlineEvent","qq":3}
+    ]}"#;
+
+    fn online_ids(events: impl IntoIterator<Item = MessageOrEvent>) -> Vec<i64> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                MessageOrEvent::Event(Event::BotOnline(event)) => event.id,
+                event => panic!("unexpected {event:?}"),
+            })
+            .collect()
+    }
+
+    fn listen_scripted(
+        events: HttpAdapterEvents,
+        responses: impl IntoIterator<Item = (u16, &'static str)>,
+        action: fn(&HttpAdapterError) -> PollAction,
+    ) -> (
+        mpsc::Receiver<MessageOrEvent>,
+        mpsc::UnboundedReceiver<HttpAdapterError>,
+    ) {
+        let (errors_tx, errors_rx) = mpsc::unbounded_channel();
+        let rx = events.listen(scripted_session(responses), move |err| {
+            let action = action(&err);
+            errors_tx.send(err).unwrap();
+            action
+        });
+        (rx, errors_rx)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drop_oldest_drops_events_that_do_not_fit() {
+        let events = HttpAdapterEvents::new()
+            .buffer(1)
+            .overflow(OverflowPolicy::DropOldest);
+        let (mut rx, mut errors) =
+            listen_scripted(events, [(200, THREE_EVENTS)], |_| PollAction::Continue);
+        let err = errors.recv().await.unwrap();
+        assert!(matches!(err, HttpAdapterError::EventsDropped(1)), "{err:?}");
+        let first = rx.recv().await.unwrap();
+        let second = rx.recv().await.unwrap();
+        assert_eq!(online_ids([first, second]), [1, 3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drop_oldest_stops_when_the_receiver_is_dropped() {
+        let events = HttpAdapterEvents::new()
+            .buffer(1)
+            .overflow(OverflowPolicy::DropOldest);
+        let session = scripted_session([(200, THREE_EVENTS); 3]);
+        let receiver = Arc::new(Mutex::new(None));
+        let (errors_tx, mut errors) = mpsc::unbounded_channel();
+        let rx = events.listen(session, {
+            let receiver = receiver.clone();
+            move |err| {
+                // drop the receiver as soon as events start being dropped, while fetches still
+                // return events
+                if let HttpAdapterError::EventsDropped(_) = err {
+                    drop(receiver.lock().unwrap().take());
+                }
+                errors_tx.send(err).unwrap();
+                PollAction::Continue
+            }
+        });
+        *receiver.lock().unwrap() = Some(rx);
+        let err = errors.recv().await.unwrap();
+        assert!(matches!(err, HttpAdapterError::EventsDropped(1)), "{err:?}");
+        match errors.recv().await.unwrap() {
+            HttpAdapterError::Undelivered(events) => {
+                assert_eq!(online_ids(events), [3, 1, 2, 3]);
+            }
+            err => panic!("unexpected {err:?}"),
+        }
+        assert!(errors.recv().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_consumer_gets_the_rest_as_undelivered() {
+        let events = HttpAdapterEvents::new()
+            .buffer(1)
+            .stall_threshold(Some(Duration::from_secs(1)));
+        let (mut rx, mut errors) =
+            listen_scripted(events, [(200, THREE_EVENTS)], |err| match err {
+                HttpAdapterError::ConsumerStalled(_) => PollAction::Stop,
+                _ => PollAction::Continue,
+            });
+        let err = errors.recv().await.unwrap();
+        assert!(
+            matches!(err, HttpAdapterError::ConsumerStalled(threshold) if threshold == Duration::from_secs(1)),
+            "{err:?}"
+        );
+        match errors.recv().await.unwrap() {
+            HttpAdapterError::Undelivered(events) => assert_eq!(online_ids(events), [2, 3]),
+            err => panic!("unexpected {err:?}"),
+        }
+        assert_eq!(online_ids([rx.recv().await.unwrap()]), [1]);
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_receiver_gets_the_batch_as_undelivered() {
+        let events = HttpAdapterEvents::new().buffer(1);
+        let (rx, mut errors) =
+            listen_scripted(events, [(200, THREE_EVENTS)], |_| PollAction::Continue);
+        drop(rx);
+        match errors.recv().await.unwrap() {
+            HttpAdapterError::Undelivered(events) => assert_eq!(online_ids(events), [1, 2, 3]),
+            err => panic!("unexpected {err:?}"),
+        }
+        assert!(errors.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn dropping_upload_file_cancels_the_request() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let base_url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let (started_tx, started_rx) = oneshot::channel();
+        let (dropped_tx, dropped_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 64 * 1024];
+            assert!(socket.read(&mut buf).await.unwrap() > 0);
+            started_tx.send(()).unwrap();
+            // stop reading so the upload stalls until it is dropped
+            dropped_rx.await.unwrap();
+            // then drain whatever was buffered; the stream ends once the client hangs up
+            while let Ok(1..) = socket.read(&mut buf).await {}
+        });
+        let session = session(base_url, DefaultFetch::new());
+        let file = Bytes::from(vec![0; 64 * 1024 * 1024]);
+        let mut upload = Box::pin(session.upload_file(1, "".into(), "file".into(), file));
+        tokio::select! {
+            result = &mut upload => panic!("upload finished early: {result:?}"),
+            started = started_rx => started.unwrap(),
+        }
+        drop(upload);
+        dropped_tx.send(()).unwrap();
+        timeout(Duration::from_secs(10), server)
+            .await
+            .expect("connection was not closed after the upload was dropped")
+            .unwrap();
+    }
+
+    // Answers each request on a connection of its own with the body scripted for its path, or a
+    // plain success, and logs the path along with how many body bytes arrived.
+    async fn serve_scripted(
+        responses: &'static [(&'static str, &'static str)],
+    ) -> (Url, mpsc::UnboundedReceiver<(String, usize)>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let base_url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = vec![0; 64 * 1024];
+                let head_len = loop {
+                    if let Some(end) = request.windows(4).position(|bytes| bytes == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                    let len = socket.read(&mut buf).await.unwrap();
+                    assert!(len > 0, "connection closed before the request head ended");
+                    request.extend_from_slice(&buf[..len]);
+                };
+                let head = String::from_utf8_lossy(&request[..head_len]).into_owned();
+                let content_length = head
+                    .lines()
+                    .find_map(|line| {
+                        line.to_ascii_lowercase()
+                            .strip_prefix("content-length:")?
+                            .trim()
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0);
+                // an interrupted upload ends early
+                while request.len() < head_len + content_length {
+                    match socket.read(&mut buf).await {
+                        Ok(len @ 1..) => request.extend_from_slice(&buf[..len]),
+                        _ => break,
+                    }
+                }
+                let path = head.split(' ').nth(1).unwrap().split('?').next().unwrap();
+                let body = responses
+                    .iter()
+                    .find(|&&(scripted, _)| scripted == path)
+                    .map_or(r#"{"code":0,"msg":"success"}"#, |&(_, body)| body);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = requests_tx.send((path.to_owned(), request.len() - head_len));
+            }
+        });
+        (base_url, requests_rx)
+    }
+
+    // A four byte file.
+    const FILE_INFO: &str = r#"{"code":0,"msg":"","data":{
+        "id": "/file", "name": "file", "path": "/file", "parent": null,
+        "contact": { "id": 987654321, "name": "Group", "permission": "MEMBER" },
+        "isFile": true, "isDirectory": false, "size": 4, "sha1": "", "md5": "",
+        "uploaderId": 10000, "uploadTime": 0, "lastModifyTime": 0,
+        "downloadInfo": { "url": "http://example.com/file" }
+    }}"#;
+
+    async fn copy_file(
+        max_bytes: usize,
+        chunks: &'static [&'static str],
+    ) -> (
+        Result<FileDetails, CopyFileError<HttpAdapterError>>,
+        Vec<String>,
+    ) {
+        let responses = &[("/file/info", FILE_INFO), ("/file/upload", FILE_INFO)];
+        let (base_url, mut requests) = serve_scripted(responses).await;
+        let session = session(base_url, DefaultFetch::new());
+        let file = mah_core::Bot.get_group(987654321).get_file("/file".into());
+        let result = file
+            .copy_to(&session, file.group(), "/", max_bytes, |_| async {
+                let chunks = chunks
+                    .iter()
+                    .map(|chunk| Ok(Bytes::from_static(chunk.as_bytes())));
+                Ok(futures_util::stream::iter(chunks))
+            })
+            .await;
+        drop(session);
+        let mut paths = Vec::new();
+        while let Ok(Some((path, _))) = timeout(Duration::from_millis(100), requests.recv()).await {
+            paths.push(path);
+        }
+        (result, paths)
+    }
+
+    #[tokio::test]
+    async fn copy_to_rejects_files_reported_too_large() {
+        let (result, paths) = copy_file(3, &["abcd"]).await;
+        assert!(
+            matches!(result, Err(CopyFileError::TooLarge(3))),
+            "{result:?}"
+        );
+        assert_eq!(paths, ["/file/info"]);
+    }
+
+    #[tokio::test]
+    async fn copy_to_deletes_copies_cut_off_at_max_bytes() {
+        // the file turns out larger than reported, and the upload ends at the last chunk that fits
+        let (result, paths) = copy_file(6, &["abcd", "efgh"]).await;
+        assert!(
+            matches!(result, Err(CopyFileError::TooLarge(6))),
+            "{result:?}"
+        );
+        assert_eq!(paths, ["/file/info", "/file/upload", "/file/delete"]);
+    }
 }
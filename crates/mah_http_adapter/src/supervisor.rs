@@ -0,0 +1,420 @@
+use std::sync::{Arc, Mutex};
+
+use mah_core::adapter::error_codes;
+use mah_core::event::{Event, MessageOrEvent};
+use mah_core::types;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{Duration, Instant};
+
+use crate::fetch::{DefaultFetch, Fetch};
+use crate::{HttpAdapter, HttpAdapterError, HttpAdapterEvents, PollAction};
+
+/// Where a [`BotSupervisor`] is in its connection cycle, as reported by [`BotSupervisor::start`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    /// Verifying and binding a new session.
+    Connecting,
+    /// Bound and polling events.
+    Connected,
+    /// Waiting out the backoff after a session was lost or could not be opened.
+    Disconnected,
+    /// The event receiver was dropped and the supervisor has exited.
+    Stopped,
+}
+
+/// Keeps a bot connected through an [`HttpAdapter`], rebinding whenever its session is lost.
+#[derive(Clone, Debug)]
+pub struct BotSupervisor<F = DefaultFetch> {
+    adapter: HttpAdapter<F>,
+    qq: i64,
+    events: HttpAdapterEvents,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    flap_dwell: Duration,
+}
+
+impl<F: Fetch + 'static> BotSupervisor<F> {
+    pub fn new(adapter: HttpAdapter<F>, qq: i64) -> Self {
+        Self {
+            adapter,
+            qq,
+            events: HttpAdapterEvents::new(),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            flap_dwell: Duration::from_secs(5),
+        }
+    }
+
+    pub fn events(self, events: HttpAdapterEvents) -> Self {
+        Self { events, ..self }
+    }
+
+    /// The delay before reconnecting starts at `min_backoff`, doubles after every failed attempt up
+    /// to `max_backoff`, and is reset once a session is bound.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_backoff` is greater than `max_backoff`.
+    pub fn backoff(self, min_backoff: Duration, max_backoff: Duration) -> Self {
+        assert!(min_backoff <= max_backoff);
+        Self {
+            min_backoff,
+            max_backoff,
+            ..self
+        }
+    }
+
+    /// How long the bot may stay offline before the session is rebuilt, so that a backend flapping
+    /// between offline and online does not cause a rebuild every time; defaults to 5 seconds.
+    pub fn flap_dwell(self, flap_dwell: Duration) -> Self {
+        Self { flap_dwell, ..self }
+    }
+
+    /// Connects in the background and returns the events of every session it opens, merged into
+    /// one receiver, along with the current [`ConnectionState`].
+    ///
+    /// The session is rebuilt when the bot stays offline for the [flap dwell](Self::flap_dwell) or
+    /// mirai reports the session as invalid. These and all other errors are passed to `on_error`.
+    ///
+    /// Dropping the receiver stops the supervisor, which then releases its session. Events fetched
+    /// but not yet delivered by then are passed to `on_error` as
+    /// [`HttpAdapterError::Undelivered`].
+    pub fn start(
+        self,
+        on_error: impl FnMut(HttpAdapterError) + Send + 'static,
+    ) -> (
+        mpsc::Receiver<MessageOrEvent>,
+        watch::Receiver<ConnectionState>,
+    ) {
+        let (tx, rx) = mpsc::channel(self.events.buffer);
+        let (state_tx, state_rx) = watch::channel(ConnectionState::Connecting);
+        let on_error = Arc::new(Mutex::new(on_error));
+        tokio::spawn(async move {
+            let Self {
+                adapter,
+                qq,
+                events,
+                min_backoff,
+                max_backoff,
+                flap_dwell,
+            } = self;
+            let mut backoff = min_backoff;
+            loop {
+                state_tx.send_replace(ConnectionState::Connecting);
+                match adapter.verify_and_bind(qq).await {
+                    Ok(session) => {
+                        state_tx.send_replace(ConnectionState::Connected);
+                        backoff = min_backoff;
+                        let session = Arc::new(session);
+                        let mut events = events.listen(session.clone(), {
+                            let on_error = on_error.clone();
+                            move |err| {
+                                let action = match err {
+                                    HttpAdapterError::Mirai(ref err)
+                                        if matches!(
+                                            err.code.get(),
+                                            error_codes::INVALID_SESSION
+                                                | error_codes::UNVERIFIED_SESSION
+                                        ) =>
+                                    {
+                                        PollAction::Stop
+                                    }
+                                    _ => PollAction::Backoff(min_backoff),
+                                };
+                                (*on_error.lock().unwrap())(err);
+                                action
+                            }
+                        });
+                        let mut undelivered = Vec::new();
+                        let mut reconnect_at = None;
+                        let mut closed = loop {
+                            let event = tokio::select! {
+                                biased;
+                                () = tx.closed() => break true,
+                                () = tokio::time::sleep_until(
+                                    reconnect_at.unwrap_or_else(Instant::now),
+                                ), if reconnect_at.is_some() => break false,
+                                event = events.recv() => event,
+                            };
+                            let Some(event) = event else {
+                                break false;
+                            };
+                            match event {
+                                MessageOrEvent::Event(
+                                    Event::BotOfflineActive(_)
+                                    | Event::BotOfflineForced(_)
+                                    | Event::BotOfflineDropped(_),
+                                ) => {
+                                    reconnect_at.get_or_insert_with(|| Instant::now() + flap_dwell);
+                                }
+                                MessageOrEvent::Event(
+                                    Event::BotOnline(_) | Event::BotRelogin(_),
+                                ) => reconnect_at = None,
+                                _ => {}
+                            }
+                            if let Err(SendError(event)) = tx.send(event).await {
+                                undelivered.push(event);
+                                break true;
+                            }
+                        };
+                        // Hand on what the listener has already fetched before releasing the
+                        // session; anything it still holds is reported through `on_error`.
+                        events.close();
+                        while let Some(event) = events.recv().await {
+                            if closed {
+                                undelivered.push(event);
+                            } else if let Err(SendError(event)) = tx.send(event).await {
+                                closed = true;
+                                undelivered.push(event);
+                            }
+                        }
+                        if !undelivered.is_empty() {
+                            (*on_error.lock().unwrap())(HttpAdapterError::Undelivered(undelivered));
+                        }
+                        // The session may already be invalid, in which case there is nothing to
+                        // release.
+                        let _ = session.release(&types::BindArgs { qq }).await;
+                        if closed {
+                            break;
+                        }
+                    }
+                    Err(err) => (*on_error.lock().unwrap())(err),
+                }
+                state_tx.send_replace(ConnectionState::Disconnected);
+                if tokio::time::timeout(backoff, tx.closed()).await.is_ok() {
+                    break;
+                }
+                backoff = (backoff * 2).min(max_backoff);
+            }
+            state_tx.send_replace(ConnectionState::Stopped);
+        });
+        (rx, state_rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+
+    use async_trait::async_trait;
+    use reqwest::{Request, Response, Url};
+
+    use super::*;
+
+    // Answers each endpoint with its scripted responses in order, then with a plain success, and
+    // logs the endpoints called along with the (paused) time of the call.
+    #[derive(Clone, Debug, Default)]
+    struct StubFetch {
+        responses: Arc<Mutex<HashMap<&'static str, VecDeque<&'static str>>>>,
+        calls: Arc<Mutex<Vec<(String, Instant)>>>,
+    }
+
+    impl StubFetch {
+        fn new(responses: impl IntoIterator<Item = (&'static str, &'static str)>) -> Self {
+            let stub = Self::default();
+            for (path, body) in responses {
+                let mut responses = stub.responses.lock().unwrap();
+                responses.entry(path).or_default().push_back(body);
+            }
+            stub
+        }
+
+        fn calls(&self, path: &str) -> Vec<Instant> {
+            let calls = self.calls.lock().unwrap();
+            calls
+                .iter()
+                .filter(|(called, _)| called == path)
+                .map(|&(_, time)| time)
+                .collect()
+        }
+
+        fn session_calls(&self) -> Vec<String> {
+            let calls = self.calls.lock().unwrap();
+            calls
+                .iter()
+                .map(|(path, _)| path.clone())
+                .filter(|path| path != "fetchMessage")
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl Fetch for StubFetch {
+        async fn fetch(&self, request: Request) -> Result<Response, reqwest::Error> {
+            let path = request.url().path().trim_start_matches('/').to_owned();
+            let scripted = self
+                .responses
+                .lock()
+                .unwrap()
+                .get_mut(path.as_str())
+                .and_then(VecDeque::pop_front);
+            let body = scripted.unwrap_or(match path.as_str() {
+                "verify" => r#"{"code":0,"session":"session"}"#,
+                "fetchMessage" => r#"{"code":0,"msg":"","data":[]}"#,
+                _ => r#"{"code":0,"msg":"success"}"#,
+            });
+            self.calls.lock().unwrap().push((path, Instant::now()));
+            Ok(http::Response::new(body).into())
+        }
+    }
+
+    const OFFLINE: &str =
+        r#"{"code":0,"msg":"","data":[{"type":"BotOfflineEventDropped","qq":10000}]}"#;
+    const ONLINE: &str = r#"{"code":0,"msg":"","data":[{"type":"BotOnlineEvent","qq":10000}]}"#;
+    const INVALID_SESSION: &str = r#"{"code":3,"msg":"invalid session"}"#;
+    const WRONG_VERIFY_KEY: &str = r#"{"code":1,"msg":"wrong verify key"}"#;
+
+    fn supervisor(fetch: &StubFetch) -> BotSupervisor<StubFetch> {
+        let endpoint = Url::parse("http://localhost/").unwrap();
+        let adapter = HttpAdapter::with_fetch(endpoint, None, fetch.clone());
+        BotSupervisor::new(adapter, 10000).events(HttpAdapterEvents::new().buffer(16))
+    }
+
+    fn collect_errors(
+        errors: &Arc<Mutex<Vec<HttpAdapterError>>>,
+    ) -> impl FnMut(HttpAdapterError) + Send + 'static {
+        let errors = errors.clone();
+        move |err| errors.lock().unwrap().push(err)
+    }
+
+    fn secs_since(start: Instant, times: &[Instant]) -> Vec<u64> {
+        times
+            .iter()
+            .map(|time| time.duration_since(start).as_secs())
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rebinds_after_the_bot_goes_offline() {
+        let fetch = StubFetch::new([("fetchMessage", OFFLINE)]);
+        let supervisor = supervisor(&fetch).flap_dwell(Duration::ZERO);
+        let (mut rx, mut state) = supervisor.start(|err| panic!("unexpected {err:?}"));
+        let event = rx.recv().await.unwrap();
+        assert!(matches!(
+            event,
+            MessageOrEvent::Event(Event::BotOfflineDropped(_))
+        ));
+        state
+            .wait_for(|state| *state == ConnectionState::Disconnected)
+            .await
+            .unwrap();
+        state
+            .wait_for(|state| *state == ConnectionState::Connected)
+            .await
+            .unwrap();
+        assert_eq!(
+            fetch.session_calls(),
+            ["verify", "bind", "release", "verify", "bind"]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn coming_back_online_within_the_dwell_does_not_rebind() {
+        let fetch = StubFetch::new([("fetchMessage", OFFLINE), ("fetchMessage", ONLINE)]);
+        let (mut rx, _state) = supervisor(&fetch).start(|err| panic!("unexpected {err:?}"));
+        rx.recv().await.unwrap();
+        rx.recv().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(fetch.session_calls(), ["verify", "bind"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn staying_offline_for_the_dwell_rebinds() {
+        let start = Instant::now();
+        let fetch = StubFetch::new([("fetchMessage", OFFLINE)]);
+        let (_rx, _state) = supervisor(&fetch).start(|err| panic!("unexpected {err:?}"));
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(
+            fetch.session_calls(),
+            ["verify", "bind", "release", "verify", "bind"]
+        );
+        // rebuilt after the 5 second dwell and the 1 second backoff
+        assert_eq!(secs_since(start, &fetch.calls("verify")), [0, 6]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rebinds_after_the_session_is_invalidated() {
+        let fetch = StubFetch::new([("fetchMessage", INVALID_SESSION)]);
+        let errors = Arc::default();
+        let (_rx, _state) = supervisor(&fetch).start(collect_errors(&errors));
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(
+            fetch.session_calls(),
+            ["verify", "bind", "release", "verify", "bind"]
+        );
+        let errors = errors.lock().unwrap();
+        assert!(
+            matches!(errors[..], [HttpAdapterError::Mirai(ref err)] if err.code.get() == 3),
+            "{errors:?}"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff_doubles_up_to_the_maximum_and_resets_once_bound() {
+        let start = Instant::now();
+        let fetch = StubFetch::new([
+            ("verify", WRONG_VERIFY_KEY),
+            ("verify", WRONG_VERIFY_KEY),
+            ("verify", WRONG_VERIFY_KEY),
+            ("fetchMessage", INVALID_SESSION),
+        ]);
+        let supervisor = supervisor(&fetch).backoff(Duration::from_secs(1), Duration::from_secs(2));
+        let (_rx, _state) = supervisor.start(|_| {});
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let verified = fetch.calls("verify");
+        assert_eq!(secs_since(start, &verified[..5]), [0, 1, 3, 5, 6]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_its_connection_state() {
+        let fetch = StubFetch::new([("verify", WRONG_VERIFY_KEY)]);
+        let (rx, mut state) = supervisor(&fetch).start(|_| {});
+        assert_eq!(*state.borrow(), ConnectionState::Connecting);
+        state
+            .wait_for(|state| *state == ConnectionState::Disconnected)
+            .await
+            .unwrap();
+        state
+            .wait_for(|state| *state == ConnectionState::Connected)
+            .await
+            .unwrap();
+        drop(rx);
+        state
+            .wait_for(|state| *state == ConnectionState::Stopped)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropping_the_receiver_releases_and_reports_undelivered_events() {
+        let fetch = StubFetch::new([(
+            "fetchMessage",
+            r#"{"code":0,"msg":"","data":[
+                {"type":"BotOnlineEvent","qq":1},
+                {"type":"BotOnlineEvent","qq":2},
+                {"type":"BotOnlineEvent","qq":3}
+            ]}"#,
+        )]);
+        let errors = Arc::default();
+        let (rx, mut state) = supervisor(&fetch).start(collect_errors(&errors));
+        drop(rx);
+        state
+            .wait_for(|state| *state == ConnectionState::Stopped)
+            .await
+            .unwrap();
+        assert_eq!(fetch.session_calls(), ["verify", "bind", "release"]);
+        let mut undelivered = Vec::new();
+        for err in errors.lock().unwrap().drain(..) {
+            let HttpAdapterError::Undelivered(events) = err else {
+                panic!("unexpected {err:?}");
+            };
+            undelivered.extend(events.into_iter().map(|event| match event {
+                MessageOrEvent::Event(Event::BotOnline(event)) => event.id,
+                event => panic!("unexpected {event:?}"),
+            }));
+        }
+        undelivered.sort_unstable();
+        assert_eq!(undelivered, [1, 2, 3]);
+    }
+}
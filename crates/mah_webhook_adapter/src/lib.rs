@@ -1,44 +1,120 @@
 #![forbid(unsafe_code)]
 
-use std::convert::Infallible;
-use std::net::SocketAddr;
-
+#[cfg(any(feature = "warp", feature = "axum"))]
 use mah_core::event::MessageOrEvent;
+#[cfg(feature = "warp")]
+use mah_core::event::RawMessageOrEvent;
+#[cfg(any(feature = "warp", feature = "axum"))]
 use tokio::sync::mpsc;
-use warp::{Filter as _, Rejection};
 
 #[derive(Clone, Copy, Debug)]
-pub struct WebhookAdapterEvents(());
+pub struct WebhookAdapterEvents {
+    #[cfg(feature = "warp")]
+    body_timeout: std::time::Duration,
+}
 
 impl WebhookAdapterEvents {
     pub fn new() -> Self {
-        Self(())
+        Self {
+            #[cfg(feature = "warp")]
+            body_timeout: std::time::Duration::from_secs(10),
+        }
+    }
+
+    /// Limits how long the built-in server waits for a request body to arrive in full. Requests
+    /// exceeding it are rejected with [`BodyRejection::Timeout`].
+    #[cfg(feature = "warp")]
+    pub fn body_timeout(self, body_timeout: std::time::Duration) -> Self {
+        Self { body_timeout }
+    }
+
+    /// Stops the built-in server once `signal` completes, in addition to when the receiver is
+    /// dropped. To stop on a `tokio_util::sync::CancellationToken`, pass
+    /// `token.cancelled_owned()`.
+    #[cfg(feature = "warp")]
+    pub fn shutdown_on<S: std::future::Future<Output = ()> + Send + 'static>(
+        self,
+        signal: S,
+    ) -> CancellableWebhookAdapterEvents<S> {
+        CancellableWebhookAdapterEvents {
+            events: self,
+            signal,
+        }
     }
 
+    #[cfg(feature = "warp")]
     pub fn listen(
         self,
-        addr: impl Into<SocketAddr>,
-        on_error: impl Fn(Rejection) + Clone + Send + Sync + 'static,
-    ) -> Result<mpsc::UnboundedReceiver<MessageOrEvent>, warp::Error> {
-        let addr = addr.into();
+        addr: impl Into<std::net::SocketAddr>,
+        on_error: impl Fn(warp::Rejection) + Clone + Send + Sync + 'static,
+    ) -> Result<
+        (
+            std::net::SocketAddr,
+            mpsc::UnboundedReceiver<MessageOrEvent>,
+        ),
+        warp::Error,
+    > {
+        self.shutdown_on(std::future::pending())
+            .listen(addr, on_error)
+    }
+
+    #[cfg(feature = "warp")]
+    pub fn listen_with_listener(
+        self,
+        listener: std::net::TcpListener,
+        on_error: impl Fn(warp::Rejection) + Clone + Send + Sync + 'static,
+    ) -> std::io::Result<(
+        std::net::SocketAddr,
+        mpsc::UnboundedReceiver<MessageOrEvent>,
+    )> {
+        self.shutdown_on(std::future::pending())
+            .listen_with_listener(listener, on_error)
+    }
+
+    #[cfg(feature = "warp")]
+    pub fn listen_raw(
+        self,
+        addr: impl Into<std::net::SocketAddr>,
+        on_error: impl Fn(warp::Rejection) + Clone + Send + Sync + 'static,
+    ) -> Result<
+        (
+            std::net::SocketAddr,
+            mpsc::UnboundedReceiver<RawMessageOrEvent>,
+        ),
+        warp::Error,
+    > {
+        self.shutdown_on(std::future::pending())
+            .listen_raw(addr, on_error)
+    }
+
+    #[cfg(feature = "axum")]
+    pub fn router(
+        self,
+        on_error: impl Fn(axum::extract::rejection::JsonRejection) + Clone + Send + Sync + 'static,
+    ) -> (axum::Router, mpsc::UnboundedReceiver<MessageOrEvent>) {
+        use axum::extract::rejection::JsonRejection;
+        use axum::extract::DefaultBodyLimit;
+        use axum::http::StatusCode;
+        use axum::Json;
+
         let (tx, rx) = mpsc::unbounded_channel();
-        let route = warp::body::content_length_limit(0x10000)
-            .and(warp::body::json())
-            .map({
-                let tx = tx.clone();
-                move |value| {
-                    let _ = tx.send(value);
-                    warp::http::StatusCode::NO_CONTENT
-                }
-            })
-            .recover(move |err| {
-                on_error(err);
-                std::future::ready(Ok::<_, Infallible>(warp::http::StatusCode::BAD_REQUEST))
-            });
-        let (_, server) = warp::serve(route)
-            .try_bind_with_graceful_shutdown(addr, async move { tx.closed().await })?;
-        tokio::spawn(server);
-        Ok(rx)
+        let router = axum::Router::new()
+            .fallback(
+                move |body: Result<Json<MessageOrEvent>, JsonRejection>| async move {
+                    match body {
+                        Ok(Json(value)) => {
+                            let _ = tx.send(value);
+                            StatusCode::NO_CONTENT
+                        }
+                        Err(err) => {
+                            on_error(err);
+                            StatusCode::BAD_REQUEST
+                        }
+                    }
+                },
+            )
+            .layer(DefaultBodyLimit::max(0x10000));
+        (router, rx)
     }
 }
 
@@ -47,3 +123,289 @@ impl Default for WebhookAdapterEvents {
         Self::new()
     }
 }
+
+#[cfg(feature = "warp")]
+#[derive(Clone, Copy, Debug)]
+pub struct CancellableWebhookAdapterEvents<S> {
+    events: WebhookAdapterEvents,
+    signal: S,
+}
+
+#[cfg(feature = "warp")]
+impl<S: std::future::Future<Output = ()> + Send + 'static> CancellableWebhookAdapterEvents<S> {
+    pub fn listen(
+        self,
+        addr: impl Into<std::net::SocketAddr>,
+        on_error: impl Fn(warp::Rejection) + Clone + Send + Sync + 'static,
+    ) -> Result<
+        (
+            std::net::SocketAddr,
+            mpsc::UnboundedReceiver<MessageOrEvent>,
+        ),
+        warp::Error,
+    > {
+        serve(addr.into(), self.events.body_timeout, on_error, self.signal)
+    }
+
+    pub fn listen_with_listener(
+        self,
+        listener: std::net::TcpListener,
+        on_error: impl Fn(warp::Rejection) + Clone + Send + Sync + 'static,
+    ) -> std::io::Result<(
+        std::net::SocketAddr,
+        mpsc::UnboundedReceiver<MessageOrEvent>,
+    )> {
+        serve_listener(listener, self.events.body_timeout, on_error, self.signal)
+    }
+
+    pub fn listen_raw(
+        self,
+        addr: impl Into<std::net::SocketAddr>,
+        on_error: impl Fn(warp::Rejection) + Clone + Send + Sync + 'static,
+    ) -> Result<
+        (
+            std::net::SocketAddr,
+            mpsc::UnboundedReceiver<RawMessageOrEvent>,
+        ),
+        warp::Error,
+    > {
+        serve(addr.into(), self.events.body_timeout, on_error, self.signal)
+    }
+}
+
+#[cfg(feature = "warp")]
+const BODY_LIMIT: usize = 0x10000;
+
+#[cfg(feature = "warp")]
+#[derive(Debug)]
+pub enum BodyRejection {
+    Timeout,
+    TooLarge,
+    Read(warp::Error),
+    Json(serde_json::Error),
+}
+
+#[cfg(feature = "warp")]
+impl warp::reject::Reject for BodyRejection {}
+
+#[cfg(feature = "warp")]
+async fn read_body<T: serde::de::DeserializeOwned>(
+    body: impl futures_util::Stream<Item = Result<impl warp::Buf, warp::Error>>,
+    body_timeout: std::time::Duration,
+) -> Result<T, warp::Rejection> {
+    use futures_util::TryStreamExt as _;
+
+    // the declared content length is only checked up front, so count the bytes actually received
+    let read =
+        body.map_err(BodyRejection::Read)
+            .try_fold(Vec::new(), |mut bytes, chunk| async move {
+                if bytes.len() + chunk.remaining() > BODY_LIMIT {
+                    return Err(BodyRejection::TooLarge);
+                }
+                bytes.extend_from_slice(chunk.chunk());
+                Ok(bytes)
+            });
+    let bytes = tokio::time::timeout(body_timeout, read)
+        .await
+        .map_err(|_| BodyRejection::Timeout)??;
+    Ok(serde_json::from_slice(&bytes).map_err(BodyRejection::Json)?)
+}
+
+#[cfg(feature = "warp")]
+fn route<T: serde::de::DeserializeOwned + Send + 'static>(
+    tx: mpsc::UnboundedSender<T>,
+    body_timeout: std::time::Duration,
+    on_error: impl Fn(warp::Rejection) + Clone + Send + Sync + 'static,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible>
+       + Clone
+       + Send
+       + Sync
+       + 'static {
+    use std::convert::Infallible;
+
+    use warp::Filter as _;
+
+    // unlike `warp::body::content_length_limit`, accept chunked bodies, which `read_body` caps
+    warp::header::optional::<u64>("content-length")
+        .and_then(|length: Option<u64>| async move {
+            match length {
+                Some(length) if length > BODY_LIMIT as u64 => {
+                    Err(warp::reject::custom(BodyRejection::TooLarge))
+                }
+                _ => Ok(()),
+            }
+        })
+        .untuple_one()
+        .and(warp::body::stream())
+        .and_then(move |body| read_body(body, body_timeout))
+        .map(move |value| {
+            let _ = tx.send(value);
+            warp::http::StatusCode::NO_CONTENT
+        })
+        .recover(move |err| {
+            on_error(err);
+            std::future::ready(Ok::<_, Infallible>(warp::http::StatusCode::BAD_REQUEST))
+        })
+}
+
+#[cfg(feature = "warp")]
+async fn shutdown<T>(tx: mpsc::UnboundedSender<T>, signal: impl std::future::Future<Output = ()>) {
+    let closed = std::pin::pin!(tx.closed());
+    let signal = std::pin::pin!(signal);
+    futures_util::future::select(closed, signal).await;
+}
+
+#[cfg(feature = "warp")]
+fn serve<T: serde::de::DeserializeOwned + Send + 'static>(
+    addr: std::net::SocketAddr,
+    body_timeout: std::time::Duration,
+    on_error: impl Fn(warp::Rejection) + Clone + Send + Sync + 'static,
+    signal: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Result<(std::net::SocketAddr, mpsc::UnboundedReceiver<T>), warp::Error> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let (addr, server) = warp::serve(route(tx.clone(), body_timeout, on_error))
+        .try_bind_with_graceful_shutdown(addr, shutdown(tx, signal))?;
+    tokio::spawn(server);
+    Ok((addr, rx))
+}
+
+#[cfg(feature = "warp")]
+fn serve_listener<T: serde::de::DeserializeOwned + Send + 'static>(
+    listener: std::net::TcpListener,
+    body_timeout: std::time::Duration,
+    on_error: impl Fn(warp::Rejection) + Clone + Send + Sync + 'static,
+    signal: impl std::future::Future<Output = ()> + Send + 'static,
+) -> std::io::Result<(std::net::SocketAddr, mpsc::UnboundedReceiver<T>)> {
+    let addr = listener.local_addr()?;
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    let incoming = futures_util::stream::poll_fn(move |cx| {
+        listener
+            .poll_accept(cx)
+            .map(|result| Some(result.map(|(stream, _)| stream)))
+    });
+    let (tx, rx) = mpsc::unbounded_channel();
+    let server = warp::serve(route(tx.clone(), body_timeout, on_error))
+        .serve_incoming_with_graceful_shutdown(incoming, shutdown(tx, signal));
+    tokio::spawn(server);
+    Ok((addr, rx))
+}
+
+#[cfg(all(test, feature = "warp"))]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::TcpStream;
+
+    use super::*;
+
+    type Errors = mpsc::UnboundedReceiver<warp::Rejection>;
+
+    type Events = mpsc::UnboundedReceiver<MessageOrEvent>;
+
+    fn listen(events: WebhookAdapterEvents) -> (SocketAddr, Events, Errors) {
+        let (errors_tx, errors) = mpsc::unbounded_channel();
+        let (addr, rx) = events
+            .listen((Ipv4Addr::LOCALHOST, 0), move |err| {
+                let _ = errors_tx.send(err);
+            })
+            .unwrap();
+        (addr, rx, errors)
+    }
+
+    async fn response_status(stream: &mut TcpStream) -> String {
+        let mut response = vec![0; 1024];
+        let len = stream.read(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response[..len]);
+        response.lines().next().unwrap_or_default().to_owned()
+    }
+
+    async fn post_event(addr: SocketAddr) -> String {
+        let body = r#"{"type":"BotOnlineEvent","qq":10000}"#;
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len(),
+        );
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        response_status(&mut stream).await
+    }
+
+    fn body_rejection(err: &warp::Rejection) -> &BodyRejection {
+        err.find::<BodyRejection>()
+            .unwrap_or_else(|| panic!("unexpected {err:?}"))
+    }
+
+    #[tokio::test]
+    async fn slow_body_times_out() {
+        let events = WebhookAdapterEvents::new().body_timeout(Duration::from_millis(100));
+        let (addr, _events, mut errors) = listen(events);
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\n{";
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let err = errors.recv().await.unwrap();
+        assert!(matches!(body_rejection(&err), BodyRejection::Timeout));
+        assert_eq!(
+            response_status(&mut stream).await,
+            "HTTP/1.1 400 Bad Request"
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_chunked_body_is_rejected() {
+        let (addr, _events, mut errors) = listen(WebhookAdapterEvents::new());
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let head = "POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n";
+        stream.write_all(head.as_bytes()).await.unwrap();
+        let chunk = vec![b' '; BODY_LIMIT + 1];
+        let size = format!("{:x}\r\n", chunk.len());
+        stream.write_all(size.as_bytes()).await.unwrap();
+        stream.write_all(&chunk).await.unwrap();
+        stream.write_all(b"\r\n0\r\n\r\n").await.unwrap();
+        let err = errors.recv().await.unwrap();
+        assert!(matches!(body_rejection(&err), BodyRejection::TooLarge));
+        assert_eq!(
+            response_status(&mut stream).await,
+            "HTTP/1.1 400 Bad Request"
+        );
+    }
+
+    #[tokio::test]
+    async fn listens_on_a_given_listener() {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let bound = listener.local_addr().unwrap();
+        let (addr, mut rx) = WebhookAdapterEvents::new()
+            .listen_with_listener(listener, |err| panic!("unexpected {err:?}"))
+            .unwrap();
+        assert_eq!(addr, bound);
+        assert_eq!(post_event(addr).await, "HTTP/1.1 204 No Content");
+        assert!(rx.recv().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn listen_returns_the_bound_port() {
+        let (addr, _events, _errors) = listen(WebhookAdapterEvents::new());
+        assert_ne!(addr.port(), 0);
+        assert_eq!(post_event(addr).await, "HTTP/1.1 204 No Content");
+    }
+
+    #[tokio::test]
+    async fn shutdown_signal_stops_the_server() {
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let (addr, mut rx) = WebhookAdapterEvents::new()
+            .shutdown_on(async {
+                let _ = stop_rx.await;
+            })
+            .listen((Ipv4Addr::LOCALHOST, 0), |err| panic!("unexpected {err:?}"))
+            .unwrap();
+        assert_eq!(post_event(addr).await, "HTTP/1.1 204 No Content");
+        assert!(rx.recv().await.is_some());
+        stop_tx.send(()).unwrap();
+        // the receiver is still alive, yet the server is gone
+        let closed = tokio::time::timeout(Duration::from_secs(5), rx.recv());
+        assert!(closed.await.unwrap().is_none());
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}